@@ -0,0 +1,79 @@
+mod portal;
+mod wlr;
+mod x11;
+
+use std::env;
+
+use image::ImageBuffer;
+use winit::{dpi::PhysicalPosition, window::Window};
+
+pub type RgbaBuffer = ImageBuffer<image::Rgba<u8>, Vec<u8>>;
+
+/// A screenshot of the monitor under the cursor.
+pub struct Capture {
+    pub image: RgbaBuffer,
+    /// Top left corner of the monitor on the virtual desktop.
+    pub position: PhysicalPosition<i32>,
+}
+
+/// Captures directly from the X server or a wlroots compositor where possible,
+/// other Wayland sessions go through the desktop portal.
+///
+/// With `include_cursor` the mouse cursor is part of the image. Nothing when the capture
+/// failed or was cancelled.
+pub fn capture(window: &Window, cursor_position: PhysicalPosition<u32>, include_cursor: bool) -> Option<Capture> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        wlr::capture(cursor_position, include_cursor).or_else(|| portal::capture(cursor_position, include_cursor))
+    } else {
+        Some(x11::capture(window, cursor_position, include_cursor))
+    }
+}
+
+/// The windows on the captured monitor, topmost first, as `(id, x, y, width, height)` relative
+/// to its top left corner. Empty on Wayland, where clients can't see other windows.
+pub fn windows(capture: &Capture) -> Vec<(u32, i32, i32, u32, u32)> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        return vec![];
+    }
+    let windows = match x11::windows() {
+        Ok(windows) => windows,
+        Err(e) => {
+            eprintln!("Could not list the windows: {}", e);
+            return vec![];
+        }
+    };
+    let (width, height) = (capture.image.width() as i32, capture.image.height() as i32);
+    windows.into_iter()
+        .map(|(id, (left, top, right, bottom))| (id, left - capture.position.x, top - capture.position.y, right - capture.position.x, bottom - capture.position.y))
+        .filter(|&(_, left, top, right, bottom)| left < width && top < height && right > 0 && bottom > 0)
+        .map(|(id, left, top, right, bottom)| (id, left, top, (right - left) as u32, (bottom - top) as u32))
+        .collect()
+}
+
+/// Captures only the focused window, see `x11::active_window`.
+///
+/// Wayland doesn't tell clients which window has focus, so this is X11 only.
+pub fn active_window(window: &Window, include_cursor: bool, decorations: bool) -> Option<RgbaBuffer> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        eprintln!("Capturing the active window is not supported on Wayland");
+        return None;
+    }
+    match x11::active_window(window, include_cursor, decorations) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            eprintln!("Could not capture the active window: {}", e);
+            None
+        }
+    }
+}
+
+/// Captures a window from `windows` that was picked in the overlay, see `x11::window`.
+pub fn window(id: u32, include_cursor: bool) -> Option<RgbaBuffer> {
+    match x11::window(id, include_cursor) {
+        Ok(image) => Some(image),
+        Err(e) => {
+            eprintln!("Could not capture the window: {}", e);
+            None
+        }
+    }
+}
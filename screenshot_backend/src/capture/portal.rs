@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use dirs::home_dir;
+use percent_encoding::percent_decode_str;
+use winit::dpi::PhysicalPosition;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+use super::Capture;
+
+const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
+// ScreenCast source types and persist modes, see the portal documentation.
+const SOURCE_MONITOR: u32 = 1;
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+
+type Results = HashMap<String, OwnedValue>;
+
+// A monitor as reported by the ScreenCast portal, in compositor coordinates.
+struct Stream {
+    position: (i32, i32),
+    size: (i32, i32),
+}
+
+/// Captures the monitor under the cursor through xdg-desktop-portal. Nothing when
+/// there is no portal or the user cancels its dialog.
+///
+/// The Screenshot interface provides the image of the whole desktop and the
+/// ScreenCast interface tells where each monitor is on it. Without a usable
+/// ScreenCast session the whole desktop is returned.
+///
+/// The Screenshot interface has no way to include the cursor. ScreenCast only
+/// provides it as stream metadata, which would mean reading the PipeWire stream.
+pub fn capture(cursor_position: PhysicalPosition<u32>, include_cursor: bool) -> Option<Capture> {
+    if include_cursor {
+        eprintln!("The cursor can't be included in captures made through the portal");
+    }
+    let connection = match Connection::session() {
+        Ok(connection) => connection,
+        Err(e) => {
+            eprintln!("Could not connect to the session bus: {}", e);
+            return None;
+        }
+    };
+    let desktop = match screenshot(&connection) {
+        Ok(desktop) => desktop,
+        Err(e) => {
+            eprintln!("Could not take a screenshot through the portal: {}", e);
+            return None;
+        }
+    };
+
+    let streams = match monitors(&connection) {
+        Ok(streams) => streams,
+        Err(e) => {
+            eprintln!("Could not get the monitor layout from the ScreenCast portal: {}", e);
+            vec![]
+        }
+    };
+    let cursor = (cursor_position.x as i32, cursor_position.y as i32);
+    let stream = streams.iter()
+        .find(|stream| {
+            cursor.0 >= stream.position.0 && cursor.0 < stream.position.0 + stream.size.0 &&
+                cursor.1 >= stream.position.1 && cursor.1 < stream.position.1 + stream.size.1
+        })
+        .or_else(|| streams.first());
+    let stream = match stream {
+        Some(stream) => stream,
+        None => return Some(Capture { image: desktop, position: PhysicalPosition::new(0, 0) }),
+    };
+
+    // Stream geometry is in logical pixels, the screenshot might be scaled.
+    let desktop_width = streams.iter().map(|stream| stream.position.0 + stream.size.0).max().unwrap();
+    let scale = desktop.width() as f32 / desktop_width as f32;
+    let scaled = |value: i32| (value.max(0) as f32 * scale).round() as u32;
+    let image = image::imageops::crop_imm(
+        &desktop,
+        scaled(stream.position.0), scaled(stream.position.1),
+        scaled(stream.size.0), scaled(stream.size.1),
+    ).to_image();
+    // Kept in compositor coordinates, which is how the overlay finds the output again.
+    Some(Capture { image, position: PhysicalPosition::new(stream.position.0, stream.position.1) })
+}
+
+fn screenshot(connection: &Connection) -> Result<super::RgbaBuffer, Box<dyn std::error::Error>> {
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("interactive", false.into());
+    let results = request(connection, "org.freedesktop.portal.Screenshot", "Screenshot", options, |options| ("", options))?;
+
+    let uri: String = results.get("uri").ok_or("the portal returned no file")?.clone().try_into()?;
+    let path = file_path(&uri).ok_or_else(|| format!("the portal returned {}, which is not a local file", uri))?;
+    let image = image::open(&path);
+    // The portal saves the screenshot for us, we don't want it lying around.
+    fs::remove_file(&path).ok();
+    Ok(image?.to_rgba8())
+}
+
+// The path a `file://` URI points to. GNOME names screenshots like "Screenshot from ...",
+// so the spaces and other escapes have to be decoded.
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // The URI may name the host, which can only be this one.
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(OsString::from_vec(percent_decode_str(path).collect())))
+}
+
+fn monitors(connection: &Connection) -> Result<Vec<Stream>, Box<dyn std::error::Error>> {
+    const INTERFACE: &str = "org.freedesktop.portal.ScreenCast";
+
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("session_handle_token", token().into());
+    let results = request(connection, INTERFACE, "CreateSession", options, |options| (options,))?;
+    let session: String = results.get("session_handle").ok_or("the portal returned no session")?.clone().try_into()?;
+    let session = ObjectPath::try_from(session)?;
+
+    // A restore token lets the portal skip the permission dialog next time.
+    let token_path = home_dir().ok_or("there is no home directory")?.join(".sss/screencast_token");
+    let restore_token = fs::read_to_string(&token_path).unwrap_or_default();
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("types", SOURCE_MONITOR.into());
+    options.insert("multiple", true.into());
+    options.insert("persist_mode", PERSIST_UNTIL_REVOKED.into());
+    if !restore_token.is_empty() {
+        options.insert("restore_token", restore_token.as_str().into());
+    }
+    request(connection, INTERFACE, "SelectSources", options, |options| (&session, options))?;
+
+    let options: HashMap<&str, Value> = HashMap::new();
+    let results = request(connection, INTERFACE, "Start", options, |options| (&session, "", options))?;
+
+    // Nothing is ever read from the streams, so the session can go right away.
+    let session_proxy = Proxy::new(connection, DESTINATION, &session, "org.freedesktop.portal.Session")?;
+    session_proxy.call::<_, _, ()>("Close", &())?;
+
+    if let Some(restore_token) = results.get("restore_token") {
+        let restore_token: String = restore_token.clone().try_into()?;
+        fs::write(&token_path, restore_token)?;
+    }
+    let streams: Vec<(u32, HashMap<String, OwnedValue>)> = results.get("streams").ok_or("the portal returned no streams")?.clone().try_into()?;
+
+    let mut monitors = vec![];
+    for (_, properties) in streams {
+        let position: (i32, i32) = match properties.get("position") {
+            Some(position) => position.clone().try_into()?,
+            None => (0, 0),
+        };
+        // The size is optional too, but without it the monitor can't be found on the screenshot.
+        let size: (i32, i32) = match properties.get("size") {
+            Some(size) => size.clone().try_into()?,
+            None => continue,
+        };
+        monitors.push(Stream { position, size });
+    }
+    Ok(monitors)
+}
+
+// Portal requests answer through a Response signal on a request object. Its path is
+// derived from our unique name and the handle token, so we can subscribe before calling.
+fn request<'a, B>(
+    connection: &Connection,
+    interface: &str,
+    method: &str,
+    mut options: HashMap<&'a str, Value<'a>>,
+    body: impl FnOnce(HashMap<&'a str, Value<'a>>) -> B,
+) -> Result<Results, Box<dyn std::error::Error>>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+{
+    let token = token();
+    let sender = connection.unique_name().ok_or("the session bus gave no unique name")?.trim_start_matches(':').replace('.', "_");
+    let request_path = format!("{}/request/{}/{}", PATH, sender, token);
+    let request = Proxy::new(connection, DESTINATION, request_path, "org.freedesktop.portal.Request")?;
+    let mut responses = request.receive_signal("Response")?;
+
+    options.insert("handle_token", token.into());
+    let proxy = Proxy::new(connection, DESTINATION, PATH, interface)?;
+    let _: OwnedObjectPath = proxy.call(method, &body(options))?;
+
+    let message = responses.next().ok_or("the portal did not answer")?;
+    let (response, results): (u32, Results) = message.body()?;
+    match response {
+        0 => Ok(results),
+        1 => Err(format!("{} was cancelled", method).into()),
+        _ => Err(format!("{} failed", method).into()),
+    }
+}
+
+fn token() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    format!("sss{}_{}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_path_decodes_escapes() {
+        assert_eq!(
+            file_path("file:///home/me/Pictures/Screenshot%20from%202024-01-01%2012-00-00.png"),
+            Some(PathBuf::from("/home/me/Pictures/Screenshot from 2024-01-01 12-00-00.png")),
+        );
+        assert_eq!(file_path("file://localhost/tmp/a%25b.png"), Some(PathBuf::from("/tmp/a%b.png")));
+    }
+
+    #[test]
+    fn file_path_rejects_other_uris() {
+        assert_eq!(file_path("/tmp/a.png"), None);
+        assert_eq!(file_path("https://example.com/a.png"), None);
+        assert_eq!(file_path("file://otherhost/tmp/a.png"), None);
+    }
+}
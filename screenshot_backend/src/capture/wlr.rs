@@ -0,0 +1,190 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsFd;
+
+use rustix::fs::{memfd_create, MemfdFlags};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_buffer::WlBuffer, wl_output::{self, WlOutput}, wl_registry::WlRegistry,
+    wl_shm::{self, WlShm}, wl_shm_pool::WlShmPool};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_protocols_wlr::screencopy::v1::client::{zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1};
+use winit::dpi::PhysicalPosition;
+
+use super::{Capture, RgbaBuffer};
+
+/// Captures the output under the cursor with the wlr-screencopy protocol.
+///
+/// Returns `None` if the compositor does not support it.
+pub fn capture(cursor_position: PhysicalPosition<u32>, include_cursor: bool) -> Option<Capture> {
+    let conn = Connection::connect_to_env().ok()?;
+    let (globals, mut queue) = registry_queue_init::<Screencopy>(&conn).ok()?;
+    let qh = queue.handle();
+    let manager: ZwlrScreencopyManagerV1 = globals.bind(&qh, 1..=3, ()).ok()?;
+    let shm: WlShm = globals.bind(&qh, 1..=1, ()).ok()?;
+    let outputs: Vec<WlOutput> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+            .map(|global| globals.registry().bind(global.name, global.version.min(2), &qh, ()))
+            .collect()
+    });
+
+    let mut screencopy = Screencopy {
+        outputs: outputs.into_iter().map(|output| (output, Output::default())).collect(),
+        buffer: None,
+        buffer_offered: false,
+        buffer_done: false,
+        y_invert: false,
+        result: None,
+    };
+    queue.roundtrip(&mut screencopy).ok()?;
+
+    let (x, y) = (cursor_position.x as i32, cursor_position.y as i32);
+    let (output, info) = screencopy.outputs.iter()
+        .find(|(_, info)| info.contains(x, y))
+        .or_else(|| screencopy.outputs.first())?;
+    let (output, position) = (output.clone(), info.position);
+
+    let frame = manager.capture_output(include_cursor as i32, &output, &qh, ());
+    // Version 3 announces every buffer type and then sends buffer_done, older ones just the shm buffer.
+    while screencopy.buffer.is_none() || (manager.version() >= 3 && !screencopy.buffer_done) {
+        queue.blocking_dispatch(&mut screencopy).ok()?;
+        // Every buffer type was announced and none of them is one we can read.
+        let announced = screencopy.buffer_done || (manager.version() < 3 && screencopy.buffer_offered);
+        let unsupported = announced && screencopy.buffer.is_none();
+        if screencopy.result == Some(false) || unsupported {
+            frame.destroy();
+            return None;
+        }
+    }
+    let (format, width, height, stride) = screencopy.buffer?;
+
+    let size = (stride * height) as usize;
+    let fd = memfd_create("sss-screencopy", MemfdFlags::CLOEXEC).ok()?;
+    let mut file = File::from(fd);
+    file.set_len(size as u64).ok()?;
+    let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+    let buffer = pool.create_buffer(0, width as i32, height as i32, stride as i32, format, &qh, ());
+    frame.copy(&buffer);
+    while screencopy.result.is_none() {
+        queue.blocking_dispatch(&mut screencopy).ok()?;
+    }
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+    if screencopy.result != Some(true) {
+        return None;
+    }
+
+    let mut data = vec![0; size];
+    file.read_exact(&mut data).ok()?;
+    let image = convert(&data, format, width, height, stride, screencopy.y_invert);
+    Some(Capture {
+        image,
+        position: PhysicalPosition::new(position.0, position.1),
+    })
+}
+
+// Shared memory formats are little endian, so ARGB8888 is stored as B, G, R, A.
+fn convert(data: &[u8], format: wl_shm::Format, width: u32, height: u32, stride: u32, y_invert: bool) -> RgbaBuffer {
+    let bgr = matches!(format, wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888);
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        let row = if y_invert { height - 1 - y } else { y };
+        for x in 0..width {
+            let i = (stride * row + 4 * x) as usize;
+            if bgr {
+                rgba.extend_from_slice(&[data[i + 2], data[i + 1], data[i], 255]);
+            } else {
+                rgba.extend_from_slice(&[data[i], data[i + 1], data[i + 2], 255]);
+            }
+        }
+    }
+    image::ImageBuffer::from_raw(width, height, rgba).unwrap()
+}
+
+#[derive(Default)]
+struct Output {
+    position: (i32, i32),
+    mode: (i32, i32),
+    scale: i32,
+}
+
+impl Output {
+    // Outputs are placed in logical coordinates, the mode is in physical pixels.
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let scale = self.scale.max(1);
+        x >= self.position.0 && x < self.position.0 + self.mode.0 / scale &&
+            y >= self.position.1 && y < self.position.1 + self.mode.1 / scale
+    }
+}
+
+struct Screencopy {
+    outputs: Vec<(WlOutput, Output)>,
+    buffer: Option<(wl_shm::Format, u32, u32, u32)>,
+    // Whether any shm buffer was offered, readable or not.
+    buffer_offered: bool,
+    buffer_done: bool,
+    y_invert: bool,
+    // Whether the copy succeeded, once it is finished.
+    result: Option<bool>,
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Screencopy {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlOutput, ()> for Screencopy {
+    fn event(screencopy: &mut Self, output: &WlOutput, event: wl_output::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let info = match screencopy.outputs.iter_mut().find(|(o, _)| o == output) {
+            Some((_, info)) => info,
+            None => return,
+        };
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => info.position = (x, y),
+            wl_output::Event::Mode { flags: WEnum::Value(flags), width, height, .. } if flags.contains(wl_output::Mode::Current) => {
+                info.mode = (width, height);
+            },
+            wl_output::Event::Scale { factor } => info.scale = factor,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for Screencopy {
+    fn event(screencopy: &mut Self, _: &ZwlrScreencopyFrameV1, event: zwlr_screencopy_frame_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer { format, width, height, stride } => {
+                screencopy.buffer_offered = true;
+                let format = match format {
+                    WEnum::Value(format) => format,
+                    WEnum::Unknown(_) => return,
+                };
+                let supported = matches!(format,
+                    wl_shm::Format::Argb8888 | wl_shm::Format::Xrgb8888 | wl_shm::Format::Abgr8888 | wl_shm::Format::Xbgr8888);
+                if supported && screencopy.buffer.is_none() {
+                    screencopy.buffer = Some((format, width, height, stride));
+                }
+            },
+            zwlr_screencopy_frame_v1::Event::BufferDone => screencopy.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Flags { flags: WEnum::Value(flags) } => {
+                screencopy.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert);
+            },
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => screencopy.result = Some(true),
+            zwlr_screencopy_frame_v1::Event::Failed => screencopy.result = Some(false),
+            _ => {}
+        }
+    }
+}
+
+macro_rules! ignore_events {
+    ($($interface:ty),*) => {
+        $(
+            impl Dispatch<$interface, ()> for Screencopy {
+                fn event(_: &mut Self, _: &$interface, _: <$interface as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+            }
+        )*
+    };
+}
+
+ignore_events!(ZwlrScreencopyManagerV1, WlShm, WlShmPool, WlBuffer);
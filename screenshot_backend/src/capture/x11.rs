@@ -0,0 +1,316 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use scrap::{Display, Capturer};
+use winit::{window::Window, dpi::PhysicalPosition, monitor::MonitorHandle};
+use x11rb::connection::Connection;
+use x11rb::protocol::composite::{ConnectionExt as _, Redirect};
+use x11rb::protocol::damage::{ConnectionExt as _, ReportLevel};
+use x11rb::protocol::xfixes::ConnectionExt as _;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _, ImageFormat, MapState};
+use x11rb::protocol::Event;
+use x11rb::NONE;
+
+use super::{Capture, RgbaBuffer};
+
+// A window redirected just for a capture is done drawing itself into its new pixmap once it
+// has been quiet this long, or given up on after the timeout.
+const REPAINT_QUIET: Duration = Duration::from_millis(20);
+const REPAINT_TIMEOUT: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+// Left, top, right and bottom edge of a window on the root window.
+type Bounds = (i32, i32, i32, i32);
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_FRAME_EXTENTS,
+    }
+}
+
+pub fn capture(window: &Window, cursor_position: PhysicalPosition<u32>, include_cursor: bool) -> Capture {
+    capture_at(window, (cursor_position.x as i32, cursor_position.y as i32), include_cursor)
+}
+
+// Captures the monitor `point` is on, or the closest one when it is on none of them.
+fn capture_at(window: &Window, point: (i32, i32), include_cursor: bool) -> Capture {
+    let (monitor_index, selected_monitor) = window.available_monitors()
+        .enumerate()
+        .min_by_key(|(_, monitor)| distance(monitor, point))
+        .unwrap();
+    let displays = Display::all().unwrap();
+    let mut imgbuffer: Option<RgbaBuffer> = None;
+    for (index, display) in displays.into_iter().enumerate() {
+        if monitor_index == index {
+            let (width, height) = (display.width(), display.height());
+            let mut capturer = Capturer::new(display).unwrap();
+            let frame = capturer.frame().unwrap();
+            let mut bitflipped = Vec::with_capacity(width * height * 4);
+            let stride = frame.len() / height;
+
+            for y in 0..height {
+                for x in 0..width {
+                    let i = stride * y + 4 * x;
+                    bitflipped.extend_from_slice(&[
+                        frame[i + 2],
+                        frame[i + 1],
+                        frame[i],
+                        255,
+                    ]);
+                }
+            }
+            imgbuffer = image::ImageBuffer::from_raw(width as u32, height as u32, bitflipped);
+        }
+    }
+    let mut capture = Capture {
+        image: imgbuffer.unwrap(),
+        position: selected_monitor.position(),
+    };
+    if include_cursor {
+        if let Err(e) = draw_cursor(&mut capture) {
+            eprintln!("Could not draw the cursor: {}", e);
+        }
+    }
+    capture
+}
+
+// How far `point` is from the monitor, squared. Monitors can be left of or above the origin.
+fn distance(monitor: &MonitorHandle, (x, y): (i32, i32)) -> i64 {
+    let (position, size) = (monitor.position(), monitor.size());
+    let dx = (position.x - x).max(x - (position.x + size.width as i32 - 1)).max(0) as i64;
+    let dy = (position.y - y).max(y - (position.y + size.height as i32 - 1)).max(0) as i64;
+    dx * dx + dy * dy
+}
+
+// The frame never contains the cursor, so the current cursor image is asked from XFixes
+// and blended in at its hotspot.
+fn draw_cursor(capture: &mut Capture) -> Result<(), Box<dyn std::error::Error>> {
+    let (conn, _) = x11rb::connect(None)?;
+    conn.xfixes_query_version(5, 0)?.reply()?;
+    let cursor = conn.xfixes_get_cursor_image()?.reply()?;
+
+    let left = cursor.x as i32 - cursor.xhot as i32 - capture.position.x;
+    let top = cursor.y as i32 - cursor.yhot as i32 - capture.position.y;
+    for (i, argb) in cursor.cursor_image.iter().enumerate() {
+        let x = left + (i % cursor.width as usize) as i32;
+        let y = top + (i / cursor.width as usize) as i32;
+        if x < 0 || y < 0 || x >= capture.image.width() as i32 || y >= capture.image.height() as i32 {
+            continue;
+        }
+        // Cursor pixels are premultiplied ARGB.
+        let [b, g, r, a] = argb.to_le_bytes();
+        let pixel = capture.image.get_pixel_mut(x as u32, y as u32);
+        // Window captures can be transparent, so alpha is blended the same way.
+        for (channel, source) in pixel.0.iter_mut().zip([r, g, b, a]) {
+            *channel = source.saturating_add((*channel as u32 * (255 - a as u32) / 255) as u8);
+        }
+    }
+    Ok(())
+}
+
+/// Captures the focused window, with the decorations drawn by the window manager or
+/// just its client area.
+///
+/// The window is read from its own XComposite pixmap, so it doesn't matter what covers it
+/// and its alpha channel is kept. Without a compositing manager the window is redirected
+/// just for the capture, and without the extension it is cut out of the capture of the
+/// monitor it is centred on.
+pub fn active_window(window: &Window, include_cursor: bool, decorations: bool) -> Result<RgbaBuffer, Box<dyn std::error::Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+    let active = conn.get_property(false, root, atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW, 0, 1)?.reply()?
+        .value32()
+        .and_then(|mut values| values.next())
+        .filter(|active| *active != NONE)
+        .ok_or("no window is active")?;
+
+    if has_composite(&conn)? {
+        return window_image(&conn, screen_num, active, include_cursor, decorations);
+    }
+
+    let (left, top, right, bottom) = bounds(&conn, &atoms, root, active, decorations)?;
+    let capture = capture_at(window, ((left + right) / 2, (top + bottom) / 2), include_cursor);
+    let (width, height) = (capture.image.width() as i32, capture.image.height() as i32);
+    let x1 = (left - capture.position.x).clamp(0, width);
+    let y1 = (top - capture.position.y).clamp(0, height);
+    let x2 = (right - capture.position.x).clamp(0, width);
+    let y2 = (bottom - capture.position.y).clamp(0, height);
+    if x1 == x2 || y1 == y2 {
+        return Err("the window is not on a monitor".into());
+    }
+    Ok(image::imageops::crop_imm(&capture.image, x1 as u32, y1 as u32, (x2 - x1) as u32, (y2 - y1) as u32).to_image())
+}
+
+/// Captures a window picked in the overlay with its decorations, like `active_window`.
+/// Fails without the XComposite extension, the overlay's own crop is used then.
+pub fn window(window: u32, include_cursor: bool) -> Result<RgbaBuffer, Box<dyn std::error::Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    if !has_composite(&conn)? {
+        return Err("the X server has no XComposite extension".into());
+    }
+    window_image(&conn, screen_num, window, include_cursor, true)
+}
+
+fn has_composite(conn: &impl Connection) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(conn.extension_information(x11rb::protocol::composite::X11_EXTENSION_NAME)?.is_some())
+}
+
+fn window_image(conn: &impl Connection, screen_num: usize, window: u32, include_cursor: bool, decorations: bool) -> Result<RgbaBuffer, Box<dyn std::error::Error>> {
+    let root = conn.setup().roots[screen_num].root;
+    let redirect = !compositing(conn, screen_num)?;
+    let mut capture = composite(conn, root, window, decorations, redirect)?;
+    if include_cursor {
+        if let Err(e) = draw_cursor(&mut capture) {
+            eprintln!("Could not draw the cursor: {}", e);
+        }
+    }
+    Ok(capture.image)
+}
+
+// A compositing manager owns the _NET_WM_CM_Sn selection. It keeps every top level window
+// redirected, so their pixmaps always hold their complete contents.
+fn compositing(conn: &impl Connection, screen_num: usize) -> Result<bool, Box<dyn std::error::Error>> {
+    let selection = conn.intern_atom(false, format!("_NET_WM_CM_S{}", screen_num).as_bytes())?.reply()?.atom;
+    Ok(conn.get_selection_owner(selection)?.reply()?.owner != NONE)
+}
+
+// Reads a window from the XComposite pixmap of its top level window, which is the frame
+// window under reparenting window managers.
+//
+// With `redirect` nothing keeps the window off screen yet. Redirecting it gives it a pixmap
+// of its own, which the window fills in when it redraws for the expose that follows. The
+// redraw is followed through the Damage extension.
+fn composite(conn: &impl Connection, root: u32, window: u32, decorations: bool, redirect: bool) -> Result<Capture, Box<dyn std::error::Error>> {
+    conn.composite_query_version(0, 4)?.reply()?;
+    let mut top_level = window;
+    loop {
+        let parent = conn.query_tree(top_level)?.reply()?.parent;
+        if parent == root {
+            break;
+        }
+        top_level = parent;
+    }
+
+    if redirect {
+        conn.damage_query_version(1, 1)?.reply()?;
+        let damage = conn.generate_id()?;
+        conn.damage_create(damage, top_level, ReportLevel::NON_EMPTY)?;
+        conn.composite_redirect_window(top_level, Redirect::AUTOMATIC)?.check()?;
+        wait_for_repaint(conn, damage)?;
+        conn.damage_destroy(damage)?;
+    }
+    let pixmap = conn.generate_id()?;
+    conn.composite_name_window_pixmap(top_level, pixmap)?;
+    let geometry = conn.get_geometry(top_level)?.reply()?;
+    let reply = conn.get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, geometry.width, geometry.height, !0)?.reply();
+    conn.free_pixmap(pixmap)?;
+    if redirect {
+        conn.composite_unredirect_window(top_level, Redirect::AUTOMATIC)?;
+        conn.flush()?;
+    }
+    let reply = reply?;
+
+    // 32 bit windows have a real alpha channel, the others leave the top byte undefined.
+    let has_alpha = reply.depth == 32;
+    let (width, height) = (geometry.width as u32, geometry.height as u32);
+    let stride = reply.data.len() / height as usize;
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let i = stride * y + 4 * x;
+            let [b, g, r, a] = [reply.data[i], reply.data[i + 1], reply.data[i + 2], reply.data[i + 3]];
+            if !has_alpha {
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            } else if a == 0 {
+                rgba.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                // X stores premultiplied alpha, PNG doesn't.
+                let unmultiply = |channel: u8| (channel as u32 * 255 / a as u32).min(255) as u8;
+                rgba.extend_from_slice(&[unmultiply(r), unmultiply(g), unmultiply(b), a]);
+            }
+        }
+    }
+    let image: RgbaBuffer = image::ImageBuffer::from_raw(width, height, rgba).unwrap();
+
+    let origin = conn.translate_coordinates(top_level, root, 0, 0)?.reply()?;
+    if decorations || top_level == window {
+        return Ok(Capture { image, position: PhysicalPosition::new(origin.dst_x as i32, origin.dst_y as i32) });
+    }
+    let offset = conn.translate_coordinates(window, top_level, 0, 0)?.reply()?;
+    let client = conn.get_geometry(window)?.reply()?;
+    let (x, y) = (offset.dst_x.max(0) as u32, offset.dst_y.max(0) as u32);
+    Ok(Capture {
+        image: image::imageops::crop_imm(&image, x, y, client.width as u32, client.height as u32).to_image(),
+        position: PhysicalPosition::new(origin.dst_x as i32 + x as i32, origin.dst_y as i32 + y as i32),
+    })
+}
+
+// Waits until the window has drawn into `damage`'s drawable and then stayed quiet for a
+// moment, since a redraw can come in several parts. An application that doesn't draw in
+// time is captured the way it is.
+fn wait_for_repaint(conn: &impl Connection, damage: u32) -> Result<(), Box<dyn std::error::Error>> {
+    conn.flush()?;
+    let start = Instant::now();
+    let mut last_damage = None;
+    while start.elapsed() < REPAINT_TIMEOUT {
+        match conn.poll_for_event()? {
+            Some(Event::DamageNotify(event)) if event.damage == damage => {
+                // Reported again only after the damage is cleared.
+                conn.damage_subtract(damage, NONE, NONE)?;
+                conn.flush()?;
+                last_damage = Some(Instant::now());
+            },
+            Some(_) => {},
+            None if last_damage.is_some_and(|last: Instant| last.elapsed() >= REPAINT_QUIET) => break,
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+    Ok(())
+}
+
+/// The managed windows that are on screen, topmost first, including their decorations.
+pub fn windows() -> Result<Vec<(u32, Bounds)>, Box<dyn std::error::Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+    let reply = conn.get_property(false, root, atoms._NET_CLIENT_LIST_STACKING, AtomEnum::WINDOW, 0, u32::MAX / 4)?.reply()?;
+    // The list goes from bottom to top.
+    let stacking: Vec<u32> = reply.value32().into_iter().flatten().collect();
+
+    let mut windows = vec![];
+    for window in stacking.into_iter().rev() {
+        // Minimized windows and windows on other workspaces are unmapped. A window can also
+        // have closed since the list was read, then its requests fail and it is left out.
+        match conn.get_window_attributes(window)?.reply() {
+            Ok(attributes) if attributes.map_state == MapState::VIEWABLE => {},
+            _ => continue,
+        }
+        if let Ok(bounds) = bounds(&conn, &atoms, root, window, true) {
+            windows.push((window, bounds));
+        }
+    }
+    Ok(windows)
+}
+
+// Where `window` is on the root window, optionally with the frame the window manager put around it.
+fn bounds(conn: &impl Connection, atoms: &Atoms, root: u32, window: u32, decorations: bool) -> Result<Bounds, Box<dyn std::error::Error>> {
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let origin = conn.translate_coordinates(window, root, 0, 0)?.reply()?;
+    let (mut left, mut top) = (origin.dst_x as i32, origin.dst_y as i32);
+    let (mut right, mut bottom) = (left + geometry.width as i32, top + geometry.height as i32);
+    if decorations {
+        // Reparenting window managers put the client inside a frame, the extents tell how far it reaches.
+        let reply = conn.get_property(false, window, atoms._NET_FRAME_EXTENTS, AtomEnum::CARDINAL, 0, 4)?.reply()?;
+        let extents: Vec<i32> = reply.value32().into_iter().flatten().map(|extent| extent as i32).collect();
+        if let [l, r, t, b] = extents[..] {
+            left -= l;
+            right += r;
+            top -= t;
+            bottom += b;
+        }
+    }
+    Ok((left, top, right, bottom))
+}
@@ -0,0 +1,58 @@
+mod wayland;
+mod x11;
+
+use std::env;
+
+/// Something the backend can put on the clipboard.
+pub enum Contents {
+    /// PNG encoded image data.
+    Image(Vec<u8>),
+    Text(String),
+}
+
+impl Contents {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Contents::Image(data) => data,
+            Contents::Text(text) => text.as_bytes(),
+        }
+    }
+}
+
+/// The clipboard of the session the backend runs in.
+#[derive(Clone)]
+pub enum Clipboard {
+    X11(x11::Clipboard),
+    Wayland(wayland::Clipboard),
+    /// Neither is available, captures are still saved to the history.
+    None,
+}
+
+impl Clipboard {
+    /// Uses the data-control protocol on Wayland. Compositors without it still share
+    /// the X11 clipboard with Xwayland, so that is used otherwise.
+    pub fn new() -> Self {
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            if let Some(clipboard) = wayland::Clipboard::new() {
+                return Clipboard::Wayland(clipboard);
+            }
+            eprintln!("The compositor does not support wlr-data-control, falling back to the X11 clipboard");
+        }
+        match x11::Clipboard::new() {
+            Some(clipboard) => Clipboard::X11(clipboard),
+            None => {
+                eprintln!("Could not connect to an X server either, captures won't be copied to the clipboard");
+                Clipboard::None
+            }
+        }
+    }
+
+    /// Takes ownership of the clipboard with new contents.
+    pub fn set(&self, contents: Contents) {
+        match self {
+            Clipboard::X11(clipboard) => clipboard.set(contents),
+            Clipboard::Wayland(clipboard) => clipboard.set(contents),
+            Clipboard::None => eprintln!("There is no clipboard to copy to"),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry::WlRegistry, wl_seat::WlSeat};
+use wayland_client::{event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+use super::Contents;
+
+const IMAGE_TYPES: &[&str] = &["image/png"];
+const TEXT_TYPES: &[&str] = &["text/plain;charset=utf-8", "text/plain", "UTF8_STRING", "TEXT", "STRING"];
+
+/// Owner of the Wayland clipboard through the wlr-data-control protocol.
+///
+/// Unlike the core protocol this works without a focused surface, the
+/// backend never shows one. Sources are created on the calling thread and
+/// their requests are answered from a dedicated thread.
+#[derive(Clone)]
+pub struct Clipboard {
+    conn: Connection,
+    qh: QueueHandle<Owner>,
+    manager: ZwlrDataControlManagerV1,
+    device: ZwlrDataControlDeviceV1,
+    source: Arc<Mutex<Option<ZwlrDataControlSourceV1>>>,
+}
+
+impl Clipboard {
+    /// Returns `None` if the compositor does not support data-control.
+    pub fn new() -> Option<Self> {
+        let conn = Connection::connect_to_env().ok()?;
+        let (globals, mut queue): (_, EventQueue<Owner>) = registry_queue_init(&conn).ok()?;
+        let qh = queue.handle();
+        let manager: ZwlrDataControlManagerV1 = globals.bind(&qh, 1..=2, ()).ok()?;
+        let seat: WlSeat = globals.bind(&qh, 1..=1, ()).ok()?;
+        let device = manager.get_data_device(&seat, &qh, ());
+
+        let source = Arc::new(Mutex::new(None));
+        let mut owner = Owner { source: source.clone() };
+        queue.roundtrip(&mut owner).ok()?;
+        thread::spawn(move || loop {
+            if let Err(e) = queue.blocking_dispatch(&mut owner) {
+                eprintln!("Clipboard connection lost: {}", e);
+                return;
+            }
+        });
+
+        Some(Self { conn, qh, manager, device, source })
+    }
+
+    /// Takes ownership of the clipboard with new contents.
+    pub fn set(&self, contents: Contents) {
+        let types = match contents {
+            Contents::Image(_) => IMAGE_TYPES,
+            Contents::Text(_) => TEXT_TYPES,
+        };
+        let source = self.manager.create_data_source(&self.qh, Arc::new(contents));
+        for mime_type in types {
+            source.offer(mime_type.to_string());
+        }
+        self.device.set_selection(Some(&source));
+        if let Some(previous) = self.source.lock().unwrap().replace(source) {
+            previous.destroy();
+        }
+        if let Err(e) = self.conn.flush() {
+            eprintln!("Clipboard error: {}", e);
+        }
+    }
+}
+
+struct Owner {
+    // The source currently set as the selection.
+    source: Arc<Mutex<Option<ZwlrDataControlSourceV1>>>,
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, Arc<Contents>> for Owner {
+    fn event(owner: &mut Self, source: &ZwlrDataControlSourceV1, event: zwlr_data_control_source_v1::Event, contents: &Arc<Contents>, _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { fd, .. } => {
+                // Only offered types are ever requested and they all get the same bytes.
+                // The reader might be slow, so don't hold up other requests.
+                let contents = contents.clone();
+                thread::spawn(move || {
+                    if let Err(e) = File::from(fd).write_all(contents.bytes()) {
+                        eprintln!("Clipboard error: {}", e);
+                    }
+                });
+            },
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                // Someone else owns the clipboard now, our contents are no longer needed.
+                let mut current = owner.source.lock().unwrap();
+                if current.as_ref() == Some(source) {
+                    *current = None;
+                }
+                source.destroy();
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for Owner {
+    fn event(_: &mut Self, _: &ZwlrDataControlDeviceV1, event: zwlr_data_control_device_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            // We never read the clipboard, offers from other clients are dropped right away.
+            zwlr_data_control_device_v1::Event::Selection { id: Some(offer) } |
+            zwlr_data_control_device_v1::Event::PrimarySelection { id: Some(offer) } => offer.destroy(),
+            zwlr_data_control_device_v1::Event::Finished => eprintln!("The clipboard is no longer available"),
+            _ => {}
+        }
+    }
+
+    event_created_child!(Owner, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+macro_rules! ignore_events {
+    ($($interface:ty),*) => {
+        $(
+            impl Dispatch<$interface, ()> for Owner {
+                fn event(_: &mut Self, _: &$interface, _: <$interface as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+            }
+        )*
+    };
+}
+
+ignore_events!(ZwlrDataControlManagerV1, ZwlrDataControlOfferV1, WlSeat);
+
+impl Dispatch<WlRegistry, GlobalListContents> for Owner {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
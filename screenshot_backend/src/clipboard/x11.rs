@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask,
+    PropMode, Property, SelectionNotifyEvent, SelectionRequestEvent, Timestamp, Window, WindowClass,
+    SELECTION_NOTIFY_EVENT,
+};
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{CURRENT_TIME, NONE};
+
+use super::Contents;
+
+// Transfers bigger than this are sent in pieces using the INCR protocol.
+const INCR_CHUNK: usize = 256 * 1024;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        CLIPBOARD_MANAGER,
+        SAVE_TARGETS,
+        TARGETS,
+        TIMESTAMP,
+        MULTIPLE,
+        ATOM_PAIR,
+        INCR,
+        UTF8_STRING,
+        TEXT,
+        TEXT_PLAIN: b"text/plain",
+        TEXT_PLAIN_UTF8: b"text/plain;charset=utf-8",
+        IMAGE_PNG: b"image/png",
+        SSS_WAKE,
+        SSS_TIMESTAMP,
+        SSS_SAVE,
+    }
+}
+
+/// Owner of the X11 `CLIPBOARD` selection.
+///
+/// The data lives in the backend, so it stays available after the frontend
+/// that produced it has exited. Requests are answered from a dedicated thread
+/// until another client takes the selection over.
+#[derive(Clone)]
+pub struct Clipboard {
+    conn: Arc<RustConnection>,
+    window: Window,
+    atoms: Atoms,
+    contents: Arc<Mutex<Option<Arc<Contents>>>>,
+}
+
+impl Clipboard {
+    /// Returns `None` if there is no X server to connect to.
+    pub fn new() -> Option<Self> {
+        let (conn, screen_num) = x11rb::connect(None).ok()?;
+        let conn = Arc::new(conn);
+        let screen = &conn.setup().roots[screen_num];
+        let window = conn.generate_id().ok()?;
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT, window, screen.root,
+            0, 0, 1, 1, 0,
+            WindowClass::INPUT_OUTPUT, screen.root_visual,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        ).ok()?;
+        let atoms = Atoms::new(conn.as_ref()).ok()?.reply().ok()?;
+        conn.flush().ok()?;
+
+        let clipboard = Self {
+            conn,
+            window,
+            atoms,
+            contents: Arc::new(Mutex::new(None)),
+        };
+        let owner = Owner::new(clipboard.clone());
+        thread::spawn(move || owner.run());
+        Some(clipboard)
+    }
+
+    /// Takes ownership of the clipboard with new contents.
+    pub fn set(&self, contents: Contents) {
+        *self.contents.lock().unwrap() = Some(Arc::new(contents));
+        // Selection ownership needs a server timestamp, which only the event thread can get us.
+        let event = ClientMessageEvent::new(32, self.window, self.atoms.SSS_WAKE, [0u32; 5]);
+        let sent = self.conn.send_event(false, self.window, EventMask::NO_EVENT, event).and_then(|_| self.conn.flush());
+        if let Err(e) = sent {
+            eprintln!("Clipboard error: {}", e);
+        }
+    }
+}
+
+// A transfer that is too big for a single property and is sent chunk by chunk.
+struct Incr {
+    target: Atom,
+    contents: Arc<Contents>,
+    offset: usize,
+}
+
+struct Owner {
+    clipboard: Clipboard,
+    owned_since: Timestamp,
+    transfers: HashMap<(Window, Atom), Incr>,
+}
+
+impl Owner {
+    fn new(clipboard: Clipboard) -> Self {
+        Self {
+            clipboard,
+            owned_since: CURRENT_TIME,
+            transfers: HashMap::new(),
+        }
+    }
+
+    fn run(mut self) {
+        loop {
+            let event = match self.clipboard.conn.wait_for_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Clipboard connection lost: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = self.handle(event) {
+                eprintln!("Clipboard error: {}", e);
+            }
+        }
+    }
+
+    fn handle(&mut self, event: Event) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.clipboard.conn.clone();
+        let atoms = self.clipboard.atoms;
+        let window = self.clipboard.window;
+        match event {
+            Event::ClientMessage(e) if e.type_ == atoms.SSS_WAKE => {
+                // Appending nothing to a property is the usual way to obtain the current server time.
+                conn.change_property8(PropMode::APPEND, window, atoms.SSS_TIMESTAMP, AtomEnum::STRING, &[])?;
+                conn.flush()?;
+            },
+            Event::PropertyNotify(e) if e.window == window && e.atom == atoms.SSS_TIMESTAMP => {
+                self.acquire(e.time)?;
+            },
+            Event::PropertyNotify(e) if e.state == Property::DELETE => {
+                self.continue_incr(e.window, e.atom)?;
+            },
+            Event::SelectionRequest(e) => {
+                self.answer(&e)?;
+            },
+            Event::SelectionClear(e) if e.selection == atoms.CLIPBOARD && e.time >= self.owned_since => {
+                // Someone else owns the clipboard now, our contents are no longer needed.
+                *self.clipboard.contents.lock().unwrap() = None;
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn acquire(&mut self, time: Timestamp) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = &self.clipboard.conn;
+        let atoms = self.clipboard.atoms;
+        let window = self.clipboard.window;
+        conn.set_selection_owner(window, atoms.CLIPBOARD, time)?;
+        if conn.get_selection_owner(atoms.CLIPBOARD)?.reply()?.owner != window {
+            return Err("could not take ownership of the clipboard".into());
+        }
+        self.owned_since = time;
+
+        // Let a running clipboard manager copy the data, so it outlives us as well.
+        if conn.get_selection_owner(atoms.CLIPBOARD_MANAGER)?.reply()?.owner != NONE {
+            let targets = self.targets();
+            conn.change_property32(PropMode::REPLACE, window, atoms.SSS_SAVE, AtomEnum::ATOM, &targets)?;
+            conn.convert_selection(window, atoms.CLIPBOARD_MANAGER, atoms.SAVE_TARGETS, atoms.SSS_SAVE, time)?;
+        }
+        conn.flush()?;
+        Ok(())
+    }
+
+    fn contents(&self) -> Option<Arc<Contents>> {
+        self.clipboard.contents.lock().unwrap().clone()
+    }
+
+    // Targets the current contents can be converted to, excluding the meta targets.
+    fn targets(&self) -> Vec<Atom> {
+        let atoms = self.clipboard.atoms;
+        match self.contents().as_deref() {
+            Some(Contents::Image(_)) => vec![atoms.IMAGE_PNG],
+            Some(Contents::Text(_)) => vec![
+                atoms.UTF8_STRING, atoms.TEXT_PLAIN_UTF8, atoms.TEXT_PLAIN, atoms.TEXT, AtomEnum::STRING.into(),
+            ],
+            None => vec![],
+        }
+    }
+
+    fn answer(&mut self, request: &SelectionRequestEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let atoms = self.clipboard.atoms;
+        // Obsolete clients pass no property and expect the target to be used instead.
+        let property = if request.property == NONE { request.target } else { request.property };
+        let converted = if request.selection != atoms.CLIPBOARD || self.contents().is_none() {
+            false
+        } else if request.target == atoms.MULTIPLE {
+            self.convert_multiple(request.requestor, property)?
+        } else {
+            self.convert(request.requestor, request.target, property)?
+        };
+
+        let event = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if converted { property } else { NONE },
+        };
+        let conn = &self.clipboard.conn;
+        conn.send_event(false, request.requestor, EventMask::NO_EVENT, event)?;
+        conn.flush()?;
+        Ok(())
+    }
+
+    fn convert_multiple(&mut self, requestor: Window, property: Atom) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.clipboard.conn.clone();
+        let atoms = self.clipboard.atoms;
+        let reply = conn.get_property(false, requestor, property, atoms.ATOM_PAIR, 0, u32::MAX / 4)?.reply()?;
+        let mut pairs: Vec<u32> = match reply.value32() {
+            Some(values) => values.collect(),
+            None => return Ok(false),
+        };
+        for pair in pairs.chunks_mut(2) {
+            if pair.len() == 2 && !self.convert(requestor, pair[0], pair[1])? {
+                // Failed conversions are reported by replacing their property with None.
+                pair[1] = NONE;
+            }
+        }
+        conn.change_property32(PropMode::REPLACE, requestor, property, atoms.ATOM_PAIR, &pairs)?;
+        Ok(true)
+    }
+
+    fn convert(&mut self, requestor: Window, target: Atom, property: Atom) -> Result<bool, Box<dyn std::error::Error>> {
+        let conn = self.clipboard.conn.clone();
+        let atoms = self.clipboard.atoms;
+        let contents = match self.contents() {
+            Some(contents) => contents,
+            None => return Ok(false),
+        };
+
+        if target == atoms.TARGETS {
+            let mut targets = vec![atoms.TARGETS, atoms.TIMESTAMP, atoms.MULTIPLE];
+            targets.extend(self.targets());
+            conn.change_property32(PropMode::REPLACE, requestor, property, AtomEnum::ATOM, &targets)?;
+            return Ok(true);
+        }
+        if target == atoms.TIMESTAMP {
+            conn.change_property32(PropMode::REPLACE, requestor, property, AtomEnum::INTEGER, &[self.owned_since])?;
+            return Ok(true);
+        }
+        if !self.targets().contains(&target) {
+            return Ok(false);
+        }
+
+        let data = contents.bytes();
+        if data.len() <= INCR_CHUNK {
+            conn.change_property8(PropMode::REPLACE, requestor, property, target, data)?;
+            return Ok(true);
+        }
+
+        // Announce the size and send the data once the requestor deletes the property.
+        conn.change_window_attributes(requestor, &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE))?;
+        conn.change_property32(PropMode::REPLACE, requestor, property, atoms.INCR, &[data.len() as u32])?;
+        self.transfers.insert((requestor, property), Incr { target, contents: contents.clone(), offset: 0 });
+        Ok(true)
+    }
+
+    fn continue_incr(&mut self, requestor: Window, property: Atom) -> Result<(), Box<dyn std::error::Error>> {
+        let conn = self.clipboard.conn.clone();
+        let transfer = match self.transfers.get_mut(&(requestor, property)) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+        let data = transfer.contents.bytes();
+        let end = (transfer.offset + INCR_CHUNK).min(data.len());
+        // The final, empty chunk tells the requestor that the transfer is complete.
+        conn.change_property8(PropMode::REPLACE, requestor, property, transfer.target, &data[transfer.offset..end])?;
+        if transfer.offset == end {
+            self.transfers.remove(&(requestor, property));
+            if !self.transfers.keys().any(|(window, _)| *window == requestor) {
+                conn.change_window_attributes(requestor, &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT))?;
+            }
+        } else {
+            transfer.offset = end;
+        }
+        conn.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+
+use dirs::home_dir;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Settings read from `~/.sss/config.toml`. Everything is optional.
+#[derive(Default)]
+pub struct Config {
+    pub capture: CaptureConfig,
+    pub history: HistoryConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Whether the mouse cursor is drawn into captures.
+    pub cursor: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// How many captures are kept.
+    pub size: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { size: 10 }
+    }
+}
+
+impl Config {
+    /// Sections that can't be read are reported and left at their defaults, everything
+    /// else still applies. The frontend reads its sections the same way.
+    pub fn load() -> Self {
+        let path = home_dir().unwrap().join(".sss/config.toml");
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Self::default(),
+        };
+        let mut table: toml::Table = match text.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                eprintln!("Invalid config {}, using the defaults: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        let mut errors = vec![];
+        let config = Self {
+            capture: section(&mut table, "capture", &mut errors),
+            history: section(&mut table, "history", &mut errors),
+        };
+        if !errors.is_empty() {
+            eprintln!("Invalid config {}, using the defaults for:\n{}", path.display(), errors.join("\n"));
+        }
+        config
+    }
+}
+
+// The `[name]` section of the config, or its defaults when it is missing or invalid.
+fn section<T: DeserializeOwned + Default>(table: &mut toml::Table, name: &str, errors: &mut Vec<String>) -> T {
+    match table.remove(name) {
+        Some(value) => value.try_into().unwrap_or_else(|e| {
+            // toml names the setting on a line of its own.
+            let message = e.to_string();
+            errors.push(match message.trim().rsplit_once("\nin `") {
+                Some((message, key)) => format!("{}.{}: {}", name, key.trim_end_matches('`'), message),
+                None => format!("[{}]: {}", name, message.trim()),
+            });
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
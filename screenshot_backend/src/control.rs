@@ -0,0 +1,84 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+use dirs::home_dir;
+use winit::event_loop::EventLoopProxy;
+
+/// What can be asked of a running backend through its control socket.
+///
+/// Wayland clients can't grab global keys, so there the compositor's own key
+/// bindings run `screenshot_backend <command>` instead.
+#[derive(Debug, Clone, Copy)]
+pub enum Command {
+    /// Same as `Super+Shift+S`.
+    Capture,
+    /// Same as `Super+Shift+V`.
+    History,
+    /// Same as `Super+Shift+W`.
+    Window,
+    /// Same as `Super+Shift+C`.
+    WindowClient,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "capture" => Ok(Command::Capture),
+            "history" => Ok(Command::History),
+            "window" => Ok(Command::Window),
+            "window-client" => Ok(Command::WindowClient),
+            _ => Err(format!("unknown command '{}'", s)),
+        }
+    }
+}
+
+fn socket_path() -> PathBuf {
+    home_dir().unwrap().join(".sss/control.sock")
+}
+
+/// Accepts commands, one per line, and forwards them to the event loop.
+pub fn listen(proxy: EventLoopProxy<Command>) {
+    let path = socket_path();
+    // A backend that didn't shut down cleanly leaves its socket behind.
+    fs::remove_file(&path).ok();
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Control connection failed: {}", e);
+                    continue;
+                }
+            };
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let reply = match line.map(|line| line.trim().parse::<Command>()) {
+                    Ok(Ok(command)) => {
+                        proxy.send_event(command).ok();
+                        "ok".to_owned()
+                    },
+                    Ok(Err(e)) => e,
+                    Err(_) => break,
+                };
+                writeln!(writer, "{}", reply).ok();
+            }
+        }
+    });
+}
+
+/// Sends a command to the running backend and returns its reply.
+pub fn send(command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket_path())?;
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim().to_owned())
+}
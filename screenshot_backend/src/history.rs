@@ -0,0 +1,111 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dirs::home_dir;
+
+pub struct Entry {
+    pub path: PathBuf,
+    /// PNG encoded image data.
+    pub data: Vec<u8>,
+}
+
+/// The most recent captures, newest first.
+///
+/// Every entry is kept in memory and as a file in `~/.sss/history`, so the
+/// history survives a restart of the backend.
+pub struct History {
+    dir: PathBuf,
+    size: usize,
+    entries: VecDeque<Entry>,
+}
+
+impl History {
+    /// Problems with the history directory are only reported, captures still work without it.
+    pub fn load(size: usize) -> Self {
+        let dir = home_dir().unwrap().join(".sss/history");
+        if let Err(e) = fs::create_dir_all(&dir) {
+            eprintln!("Could not create the history directory {}: {}", dir.display(), e);
+        }
+
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "png"))
+                .collect(),
+            Err(e) => {
+                eprintln!("Could not read the history directory {}: {}", dir.display(), e);
+                vec![]
+            }
+        };
+        // File names are timestamps, so this puts the newest capture first.
+        paths.sort_by(|a, b| b.cmp(a));
+
+        let mut history = Self { dir, size, entries: VecDeque::new() };
+        for path in paths {
+            if history.entries.len() < size {
+                match fs::read(&path) {
+                    Ok(data) => history.entries.push_back(Entry { path, data }),
+                    Err(e) => eprintln!("Could not read {}: {}", path.display(), e),
+                }
+            } else {
+                remove(&path);
+            }
+        }
+        history
+    }
+
+    /// Adds a new capture, dropping the oldest one if the history is full. A capture that
+    /// can't be saved is left out.
+    pub fn push(&mut self, data: Vec<u8>) {
+        let path = self.new_path();
+        if let Err(e) = fs::write(&path, &data) {
+            eprintln!("Could not save {} to the history: {}", path.display(), e);
+            return;
+        }
+        self.entries.push_front(Entry { path, data });
+        while self.entries.len() > self.size {
+            let oldest = self.entries.pop_back().unwrap();
+            remove(&oldest.path);
+        }
+    }
+
+    /// Moves the capture stored at `path` to the front, returning it if there is one.
+    pub fn promote(&mut self, path: &Path) -> Option<&Entry> {
+        let index = self.entries.iter().position(|entry| entry.path == path)?;
+        let mut entry = self.entries.remove(index).unwrap();
+        let new_path = self.new_path();
+        // Left where it is, it is still newest for as long as the backend runs.
+        match fs::rename(&entry.path, &new_path) {
+            Ok(()) => entry.path = new_path,
+            Err(e) => eprintln!("Could not move {} in the history: {}", entry.path.display(), e),
+        }
+        self.entries.push_front(entry);
+        self.entries.front()
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.entries.iter().map(|entry| entry.path.clone()).collect()
+    }
+
+    // Named after the current time. Entries made within the same millisecond get the
+    // following ones, which keeps them apart and in order.
+    fn new_path(&self) -> PathBuf {
+        let mut millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        loop {
+            let path = self.dir.join(format!("{}.png", millis));
+            if !path.exists() {
+                return path;
+            }
+            millis += 1;
+        }
+    }
+}
+
+fn remove(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        eprintln!("Could not remove {} from the history: {}", path.display(), e);
+    }
+}
@@ -1,20 +1,50 @@
-#![feature(drain_filter)]
+mod capture;
+mod clipboard;
+mod config;
+mod control;
+mod history;
 
-use image::{ImageBuffer};
+use clipboard::{Clipboard, Contents};
+use config::Config;
+use history::History;
 use mouse_rs::Mouse;
-use scrap::{Display, Capturer};
-use winit::{event_loop::{EventLoop, ControlFlow}, window::{WindowBuilder, Window}, event::{KeyboardInput, ElementState}, dpi::PhysicalPosition, monitor::{MonitorHandle}};
+use winit::{event_loop::{EventLoop, ControlFlow}, window::{Window, WindowBuilder}, event::{KeyboardInput, ElementState}, dpi::PhysicalPosition};
 use dirs::home_dir;
-use std::{thread};
-use std::process::Command;
+use std::{thread, fs};
+use std::io::{BufRead, BufReader, Cursor};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
 
 static SUPER: u32 = 125;
 static SHIFT: u32 = 42;
 static S: u32 = 31;
+static V: u32 = 47;
+static W: u32 = 17;
+static C: u32 = 46;
 static SSS: [u32; 3] = [SUPER, SHIFT, S];
+static SSV: [u32; 3] = [SUPER, SHIFT, V];
+static SSW: [u32; 3] = [SUPER, SHIFT, W];
+static SSC: [u32; 3] = [SUPER, SHIFT, C];
 
 fn main() {
-    let event_loop = EventLoop::new();
+    // `screenshot_backend <command>` asks the running backend to do something.
+    if let Some(command) = std::env::args().nth(1) {
+        match control::send(&command) {
+            Ok(reply) if reply == "ok" => {},
+            Ok(reply) => {
+                eprintln!("{}", reply);
+                std::process::exit(1);
+            },
+            Err(e) => {
+                eprintln!("Could not reach the backend: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::<control::Command>::with_user_event();
 
     let window = WindowBuilder::new()
         .with_decorations(false)
@@ -26,35 +56,35 @@ fn main() {
 
     let mut pressed_keys: Vec<u32> = Vec::new();
     let mouse = Mouse::new();
+    let config = Config::load();
+    let clipboard = Clipboard::new();
+    let history = Arc::new(Mutex::new(History::load(config.history.size)));
+    control::listen(event_loop.create_proxy());
     
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
 
         match event {
-            winit::event::Event::DeviceEvent { event, .. } => {
-                match event {
-                    winit::event::DeviceEvent::Key(KeyboardInput { scancode, state, .. }) => {
-                        if state == ElementState::Pressed && !pressed_keys.contains(&scancode) {
-                            pressed_keys.push(scancode);
-                        } else if state == ElementState::Released && pressed_keys.contains(&scancode) {
-                            pressed_keys.drain_filter(|sc| sc == &scancode);
-                        }
-                        if pressed_keys.starts_with(&SSS) {
-                            let pos =  mouse.get_position().unwrap();
-                            let cursor_position = PhysicalPosition::new(pos.x as u32, pos.y as u32);
-                            let (imgbuffer, monitor_handle) = screenshot(&window, cursor_position).unwrap();
-                            imgbuffer.save_with_format(format!("{}/.sss/tmp.png", home_dir().unwrap().to_str().unwrap()), image::ImageFormat::Png).unwrap();
-                            thread::spawn(move || {
-                                // args: monitor_x monitor_y path
-                                Command::new("./screenshot_frontend")
-                                    .args(&[format!("{}", monitor_handle.position().x), format!("{}", monitor_handle.position().y), 
-                                            format!("{}/.sss/tmp.png", home_dir().unwrap().to_str().unwrap())]).spawn().unwrap();
-                            });
-                        }
-                    },
-                    _ => {}
+            winit::event::Event::DeviceEvent { event: winit::event::DeviceEvent::Key(KeyboardInput { scancode, state, .. }), .. } => {
+                if state == ElementState::Pressed && !pressed_keys.contains(&scancode) {
+                    pressed_keys.push(scancode);
+                } else if state == ElementState::Released && pressed_keys.contains(&scancode) {
+                    pressed_keys.retain(|sc| sc != &scancode);
+                }
+                if pressed_keys.starts_with(&SSS) {
+                    capture(&window, &mouse, &config, &clipboard, &history);
+                } else if pressed_keys.starts_with(&SSV) {
+                    show_history(&clipboard, &history);
+                } else if pressed_keys.starts_with(&SSW) {
+                    capture_window(&window, &config, &clipboard, &history, true);
+                } else if pressed_keys.starts_with(&SSC) {
+                    capture_window(&window, &config, &clipboard, &history, false);
                 }
             },
+            winit::event::Event::UserEvent(control::Command::Capture) => capture(&window, &mouse, &config, &clipboard, &history),
+            winit::event::Event::UserEvent(control::Command::History) => show_history(&clipboard, &history),
+            winit::event::Event::UserEvent(control::Command::Window) => capture_window(&window, &config, &clipboard, &history, true),
+            winit::event::Event::UserEvent(control::Command::WindowClient) => capture_window(&window, &config, &clipboard, &history, false),
             winit::event::Event::MainEventsCleared => {
                 
             },
@@ -63,44 +93,101 @@ fn main() {
     });
 }
 
-fn screenshot(window: &Window, cursor_position: PhysicalPosition<u32>) -> Option<(ImageBuffer<image::Rgba<u8>, Vec<u8>>, MonitorHandle)> {
-    let monitors = window.available_monitors();
-    let mut selected_monitor: Option<MonitorHandle> = None;
-    let mut monitor_index: Option<usize> = None;
-    for (index, monitor) in monitors.enumerate() {
-        let pos = monitor.position();
-        let size = monitor.size();
-        if cursor_position.x >= pos.x as u32 && cursor_position.x <= pos.x as u32 + size.width && 
-            cursor_position.y >= pos.y as u32 && cursor_position.y <= pos.y as u32 + size.height {
-                selected_monitor = Some(monitor);
-                monitor_index = Some(index);
-        }
-    }
-    let displays = Display::all().unwrap();
-    let mut index: usize = 0;
-    let mut imgbuffer: Option<ImageBuffer<image::Rgba<u8>, Vec<u8>>> = None;
-    for display in displays {
-        if monitor_index.unwrap() == index {
-            let (width, height) = (display.width() as usize, display.height() as usize);
-            let mut capturer = Capturer::new(display).unwrap();
-            let frame = capturer.frame().unwrap();
-            let mut bitflipped = Vec::with_capacity(width * height * 4);
-            let stride = frame.len() / height;
+fn capture(window: &Window, mouse: &Mouse, config: &Config, clipboard: &Clipboard, history: &Arc<Mutex<History>>) {
+    // Wayland doesn't let clients query the pointer, so there the monitor at the origin is
+    // captured, or the first one when none is there.
+    let cursor_position = mouse.get_position()
+        .map(|pos| PhysicalPosition::new(pos.x as u32, pos.y as u32))
+        .unwrap_or_else(|_| PhysicalPosition::new(0, 0));
+    let capture = match capture::capture(window, cursor_position, config.capture.cursor) {
+        Some(capture) => capture,
+        None => return,
+    };
+    capture.image.save_with_format(format!("{}/.sss/tmp.png", home_dir().unwrap().to_str().unwrap()), image::ImageFormat::Png).unwrap();
+    // args: monitor_x monitor_y path window...
+    let mut args = vec![format!("{}", capture.position.x), format!("{}", capture.position.y), 
+                        format!("{}/.sss/tmp.png", home_dir().unwrap().to_str().unwrap())];
+    args.extend(capture::windows(&capture).iter().map(|(id, x, y, width, height)| format!("{},{},{},{},{}", id, x, y, width, height)));
+    spawn_frontend(args, config.capture.cursor, clipboard, history);
+}
 
-            for y in 0..height {
-                for x in 0..width {
-                    let i = stride * y + 4 * x;
-                    bitflipped.extend_from_slice(&[
-                        frame[i + 2],
-                        frame[i + 1],
-                        frame[i],
-                        255,
-                    ]);
-                }
-            }
-            imgbuffer = image::ImageBuffer::from_raw(width as u32, height as u32, bitflipped);
+// Window captures need no selection, so they skip the frontend and go straight to the clipboard.
+fn capture_window(window: &Window, config: &Config, clipboard: &Clipboard, history: &Mutex<History>, decorations: bool) {
+    let image = match capture::active_window(window, config.capture.cursor, decorations) {
+        Some(image) => image,
+        None => return,
+    };
+    let data = png(&image);
+    history.lock().unwrap().push(data.clone());
+    clipboard.set(Contents::Image(data));
+}
+
+fn png(image: &capture::RgbaBuffer) -> Vec<u8> {
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    data
+}
+
+fn show_history(clipboard: &Clipboard, history: &Arc<Mutex<History>>) {
+    // args: history path...
+    let mut args = vec!["history".to_owned()];
+    args.extend(history.lock().unwrap().paths().iter().map(|path| path.display().to_string()));
+    spawn_frontend(args, false, clipboard, history);
+}
+
+fn spawn_frontend(args: Vec<String>, include_cursor: bool, clipboard: &Clipboard, history: &Arc<Mutex<History>>) {
+    let clipboard = clipboard.clone();
+    let history = history.clone();
+    thread::spawn(move || {
+        let mut frontend = Command::new("./screenshot_frontend")
+            .args(&args)
+            .stdout(Stdio::piped()).spawn().unwrap();
+        handle_frontend_output(&mut frontend, include_cursor, &clipboard, &history);
+    });
+}
+
+// The frontend reports its results on stdout, one per line:
+//   image <path>   a PNG to put on the clipboard
+//   text <text>    text to put on the clipboard
+//   window <id> <path>
+//                  a window picked from the list, captured again on its own when possible,
+//                  otherwise the PNG cropped from the screenshot
+fn handle_frontend_output(frontend: &mut std::process::Child, include_cursor: bool, clipboard: &Clipboard, history: &Mutex<History>) {
+    let stdout = BufReader::new(frontend.stdout.take().unwrap());
+    for line in stdout.lines() {
+        let line = line.unwrap();
+        match line.split_once(' ') {
+            Some(("image", path)) => {
+                let mut history = history.lock().unwrap();
+                // Picking an entry from the history moves it to the front instead of adding it again.
+                let data = match history.promote(Path::new(path)) {
+                    Some(entry) => entry.data.clone(),
+                    None => {
+                        let data = fs::read(path).unwrap();
+                        history.push(data.clone());
+                        data
+                    }
+                };
+                clipboard.set(Contents::Image(data));
+            },
+            Some(("text", text)) => clipboard.set(Contents::Text(text.to_owned())),
+            Some(("window", window)) => {
+                let (id, path) = match window.split_once(' ').and_then(|(id, path)| Some((id.parse().ok()?, path))) {
+                    Some(window) => window,
+                    None => {
+                        eprintln!("Unknown frontend output: {}", line);
+                        continue;
+                    }
+                };
+                let data = match capture::window(id, include_cursor) {
+                    Some(image) => png(&image),
+                    None => fs::read(path).unwrap(),
+                };
+                history.lock().unwrap().push(data.clone());
+                clipboard.set(Contents::Image(data));
+            },
+            _ => eprintln!("Unknown frontend output: {}", line),
         }
-        index += 1;
     }
-    Some((imgbuffer.unwrap(), selected_monitor.unwrap()))
-}
\ No newline at end of file
+    frontend.wait().unwrap();
+}
@@ -0,0 +1,141 @@
+use image::{DynamicImage, GenericImageView};
+use serde::Deserialize;
+
+/// How a picked colour is written to the clipboard.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// `#1e90ff`
+    Hex,
+    /// `rgb(30, 144, 255)`
+    Rgb,
+    /// `hsl(210, 100%, 56%)`
+    Hsl,
+    /// `[0x1e, 0x90, 0xff]`
+    Rust,
+}
+
+impl Format {
+    const ALL: [Format; 4] = [Format::Hex, Format::Rgb, Format::Hsl, Format::Rust];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|format| *format == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn format(self, [r, g, b]: [u8; 3]) -> String {
+        match self {
+            Format::Hex => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            Format::Rgb => format!("rgb({}, {}, {})", r, g, b),
+            Format::Hsl => {
+                let (h, s, l) = hsl([r, g, b]);
+                format!("hsl({}, {}%, {}%)", h, s, l)
+            },
+            Format::Rust => format!("[0x{:02x}, 0x{:02x}, 0x{:02x}]", r, g, b),
+        }
+    }
+}
+
+/// The mean colour of the `size` by `size` square centred on `x`, `y`, leaving out
+/// the parts that are off the image.
+pub fn average(image: &DynamicImage, x: u32, y: u32, size: u32) -> [u8; 3] {
+    let radius = (size / 2) as i32;
+    let (mut sum, mut count) = ([0u32; 3], 0);
+    for y in y as i32 - radius..=y as i32 + radius {
+        for x in x as i32 - radius..=x as i32 + radius {
+            if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+                continue;
+            }
+            let pixel = image.get_pixel(x as u32, y as u32).0;
+            for (sum, channel) in sum.iter_mut().zip(pixel) {
+                *sum += channel as u32;
+            }
+            count += 1;
+        }
+    }
+    let count = count.max(1);
+    sum.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// The colour for drawing it with `ColorVertex`. The surface is sRGB, so it is
+/// converted to linear light first.
+pub fn linear(rgb: [u8; 3]) -> [f32; 4] {
+    let [r, g, b] = rgb.map(|channel| {
+        let channel = channel as f32 / 255.0;
+        if channel <= 0.04045 { channel / 12.92 } else { ((channel + 0.055) / 1.055).powf(2.4) }
+    });
+    [r, g, b, 1.0]
+}
+
+// The WCAG 2 contrast ratio between two colours, from 1 to 21.
+fn contrast(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let luminance = |rgb: [u8; 3]| {
+        let [r, g, b, _] = linear(rgb);
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    };
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The contrast ratio and which WCAG 2 levels it passes, for normal and for large text.
+pub fn contrast_summary(a: [u8; 3], b: [u8; 3]) -> String {
+    // Rounded far below what is shown first, so float error doesn't turn 21 into 20.99.
+    let ratio = (contrast(a, b) * 10000.0).round() / 10000.0;
+    let level = |name: &str, minimum: f32| format!("{} {}", name, if ratio >= minimum { "pass" } else { "fail" });
+    // Cut off rather than rounded, so a ratio just below a threshold doesn't look like it passes.
+    format!(
+        "{:.2}:1  text {} {}  large text {} {}",
+        (ratio * 100.0).floor() / 100.0,
+        level("AA", 4.5), level("AAA", 7.0), level("AA", 3.0), level("AAA", 4.5),
+    )
+}
+
+// Hue in degrees, saturation and lightness in percent.
+fn hsl(rgb: [u8; 3]) -> (u32, u32, u32) {
+    let [r, g, b] = rgb.map(|channel| channel as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0, 0, (lightness * 100.0).round() as u32);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    ((hue * 60.0).round() as u32 % 360, (saturation * 100.0).round() as u32, (lightness * 100.0).round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [u8; 3] = [255, 255, 255];
+
+    #[test]
+    fn contrast_summary_extremes() {
+        assert_eq!(contrast_summary([0, 0, 0], WHITE), "21.00:1  text AA pass AAA pass  large text AA pass AAA pass");
+        assert_eq!(contrast_summary(WHITE, WHITE), "1.00:1  text AA fail AAA fail  large text AA fail AAA fail");
+    }
+
+    #[test]
+    fn contrast_summary_is_symmetric() {
+        assert_eq!(contrast_summary([0x76, 0x76, 0x76], WHITE), contrast_summary(WHITE, [0x76, 0x76, 0x76]));
+    }
+
+    #[test]
+    fn contrast_summary_thresholds() {
+        // The greys closest to each threshold on white, on either side of it.
+        assert_eq!(contrast_summary([0x77, 0x77, 0x77], WHITE), "4.47:1  text AA fail AAA fail  large text AA pass AAA fail");
+        assert_eq!(contrast_summary([0x76, 0x76, 0x76], WHITE), "4.54:1  text AA pass AAA fail  large text AA pass AAA pass");
+        assert_eq!(contrast_summary([0x5a, 0x5a, 0x5a], WHITE), "6.89:1  text AA pass AAA fail  large text AA pass AAA pass");
+        assert_eq!(contrast_summary([0x59, 0x59, 0x59], WHITE), "7.00:1  text AA pass AAA pass  large text AA pass AAA pass");
+        assert_eq!(contrast_summary([0x95, 0x95, 0x95], WHITE), "2.99:1  text AA fail AAA fail  large text AA fail AAA fail");
+        assert_eq!(contrast_summary([0x94, 0x94, 0x94], WHITE), "3.03:1  text AA fail AAA fail  large text AA pass AAA fail");
+    }
+}
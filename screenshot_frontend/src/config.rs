@@ -0,0 +1,194 @@
+use std::fs;
+
+use dirs::home_dir;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::color::Format;
+use crate::keymap::KeymapConfig;
+
+/// The overlay's settings from `~/.sss/config.toml`, next to the backend's. Everything is optional.
+#[derive(Default)]
+pub struct Config {
+    pub picker: PickerConfig,
+    pub selection: SelectionConfig,
+    pub keymap: KeymapConfig,
+    pub theme: ThemeConfig,
+    pub render: RenderConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PickerConfig {
+    /// How picked colours are copied.
+    pub format: Format,
+    /// Width and height of the averaged area in pixels.
+    pub size: u32,
+}
+
+impl Default for PickerConfig {
+    fn default() -> Self {
+        Self { format: Format::Hex, size: 1 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SelectionConfig {
+    /// Aspect ratios the selection can be locked to, cycled with A.
+    pub ratios: Vec<Dimensions>,
+    /// Fixed selection sizes, cycled with S.
+    pub sizes: Vec<Dimensions>,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self {
+            ratios: vec![Dimensions { width: 16, height: 9 }, Dimensions { width: 4, height: 3 }],
+            sizes: vec![Dimensions { width: 1280, height: 720 }, Dimensions { width: 1920, height: 1080 }],
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// How far the screen outside the selection is faded towards `tint`, from 0 to 1.
+    pub dim: f32,
+    pub tint: Rgb,
+    /// Width of the line around the selection in pixels, 0 for none.
+    pub border_width: f32,
+    pub border_color: Rgb,
+    pub handles: HandleStyle,
+    pub handle_color: Rgb,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            dim: 0.6,
+            tint: Rgb([0, 0, 0]),
+            border_width: 1.0,
+            border_color: Rgb([255, 255, 255]),
+            handles: HandleStyle::Square,
+            handle_color: Rgb([255, 255, 255]),
+        }
+    }
+}
+
+/// How the handles for resizing the selection look.
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HandleStyle {
+    Square,
+    Circle,
+    /// The selection can still be resized at its edges and corners, it just doesn't show.
+    None,
+}
+
+#[derive(Clone, Copy, Deserialize, Default)]
+#[serde(default)]
+pub struct RenderConfig {
+    pub present_mode: PresentMode,
+    /// Draws the overlay on the CPU even when there is a GPU. It does anyway without one.
+    pub software: bool,
+}
+
+/// When finished frames are shown. Modes the GPU doesn't support fall back to `Fifo`.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresentMode {
+    /// Waits for the next refresh of the screen.
+    #[default]
+    Fifo,
+    /// Shows the newest frame on the next refresh, lower latency without tearing.
+    Mailbox,
+    /// Shows frames right away, lowest latency but may tear.
+    Immediate,
+}
+
+/// A colour written as `#1e90ff`.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub [u8; 3]);
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid colour '{}', expected something like #1e90ff", s);
+        let hex = s.strip_prefix('#').unwrap_or(&s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid());
+        Ok(Rgb([channel(0)?, channel(1)?, channel(2)?]))
+    }
+}
+
+/// A width and a height, written as `16:9` or `1280x720`.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TryFrom<String> for Dimensions {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid dimensions '{}', expected something like 16:9 or 1280x720", s);
+        let (width, height) = s.split_once([':', 'x']).ok_or_else(invalid)?;
+        let parse = |value: &str| value.trim().parse::<u32>().ok().filter(|value| *value > 0).ok_or_else(invalid);
+        Ok(Dimensions { width: parse(width)?, height: parse(height)? })
+    }
+}
+
+impl Config {
+    /// Sections and keymap entries that can't be read are reported and left at their
+    /// defaults, everything else still applies. The backend reads its sections the same way.
+    pub fn load() -> Self {
+        let path = home_dir().unwrap().join(".sss/config.toml");
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Self::default(),
+        };
+        // A broken config shouldn't keep the capture from showing up.
+        let mut table: toml::Table = match text.parse() {
+            Ok(table) => table,
+            Err(e) => {
+                eprintln!("Invalid config {}, using the defaults: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        let mut errors = vec![];
+        let config = Self {
+            picker: section(&mut table, "picker", &mut errors),
+            selection: section(&mut table, "selection", &mut errors),
+            keymap: table.remove("keymap").map_or_else(KeymapConfig::default, |value| KeymapConfig::parse(value, &mut errors)),
+            theme: section(&mut table, "theme", &mut errors),
+            render: section(&mut table, "render", &mut errors),
+        };
+        if !errors.is_empty() {
+            eprintln!("Invalid config {}, using the defaults for:\n{}", path.display(), errors.join("\n"));
+        }
+        config
+    }
+}
+
+// The `[name]` section of the config, or its defaults when it is missing or invalid.
+fn section<T: DeserializeOwned + Default>(table: &mut toml::Table, name: &str, errors: &mut Vec<String>) -> T {
+    match table.remove(name) {
+        Some(value) => value.try_into().unwrap_or_else(|e| {
+            // toml names the setting on a line of its own.
+            let message = e.to_string();
+            errors.push(match message.trim().rsplit_once("\nin `") {
+                Some((message, key)) => format!("{}.{}: {}", name, key.trim_end_matches('`'), message),
+                None => format!("[{}]: {}", name, message.trim()),
+            });
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
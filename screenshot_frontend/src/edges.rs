@@ -0,0 +1,122 @@
+use image::DynamicImage;
+
+// Neighbouring pixels whose brightness differs by more than this are on an edge,
+const CONTRAST: i16 = 32;
+// and edges shorter than this many pixels are left out.
+const MIN_LENGTH: u32 = 8;
+// How far from an edge, in pixels, the selection snaps to it.
+const SNAP_DISTANCE: i32 = 8;
+
+/// The horizontal and vertical edges in the captured image, such as the borders of
+/// windows, panels and buttons. Found once, so snapping to them is cheap.
+pub struct Edges {
+    /// Between columns `x - 1` and `x`, as runs of rows from start to end.
+    columns: Vec<Vec<(u32, u32)>>,
+    /// Between rows `y - 1` and `y`, as runs of columns.
+    rows: Vec<Vec<(u32, u32)>>,
+}
+
+impl Edges {
+    pub fn new(image: &DynamicImage) -> Self {
+        let luma = image.to_luma8();
+        let (width, height) = luma.dimensions();
+        let differs = |a: (u32, u32), b: (u32, u32)| (luma.get_pixel(a.0, a.1).0[0] as i16 - luma.get_pixel(b.0, b.1).0[0] as i16).abs() > CONTRAST;
+        Self {
+            columns: (0..=width).map(|x| runs(height, |y| x > 0 && x < width && differs((x - 1, y), (x, y)))).collect(),
+            rows: (0..=height).map(|y| runs(width, |x| y > 0 && y < height && differs((x, y - 1), (x, y)))).collect(),
+        }
+    }
+
+    /// The closest vertical edge to `x` that covers at least half of `top..bottom`.
+    pub fn snap_x(&self, x: i32, top: i32, bottom: i32) -> Option<i32> {
+        snap(&self.columns, x, top, bottom)
+    }
+
+    /// The closest horizontal edge to `y` that covers at least half of `left..right`.
+    pub fn snap_y(&self, y: i32, left: i32, right: i32) -> Option<i32> {
+        snap(&self.rows, y, left, right)
+    }
+}
+
+// Where `edge(i)` holds for at least `MIN_LENGTH` pixels in a row, out of `0..length`.
+fn runs(length: u32, edge: impl Fn(u32) -> bool) -> Vec<(u32, u32)> {
+    let mut runs = vec![];
+    let mut start = None;
+    for i in 0..=length {
+        match (start, i < length && edge(i)) {
+            (None, true) => start = Some(i),
+            (Some(first), false) => {
+                if i - first >= MIN_LENGTH {
+                    runs.push((first, i));
+                }
+                start = None;
+            },
+            _ => {}
+        }
+    }
+    runs
+}
+
+fn snap(lines: &[Vec<(u32, u32)>], position: i32, start: i32, end: i32) -> Option<i32> {
+    let span = end - start;
+    if span <= 0 {
+        return None;
+    }
+    let needed = (span / 2).max((MIN_LENGTH as i32).min(span));
+    let covered = |runs: &Vec<(u32, u32)>| -> i32 {
+        runs.iter().map(|&(first, last)| (last as i32).min(end) - (first as i32).max(start)).filter(|overlap| *overlap > 0).sum()
+    };
+    (position - SNAP_DISTANCE..=position + SNAP_DISTANCE)
+        .filter(|candidate| *candidate >= 0 && (*candidate as usize) < lines.len())
+        .filter(|candidate| covered(&lines[*candidate as usize]) >= needed)
+        .min_by_key(|candidate| (candidate - position).abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Black with white rectangles, given as left, top, right and bottom.
+    fn edges(rectangles: &[(u32, u32, u32, u32)]) -> Edges {
+        let image = RgbImage::from_fn(100, 100, |x, y| {
+            let inside = rectangles.iter().any(|&(left, top, right, bottom)| x >= left && x < right && y >= top && y < bottom);
+            if inside { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) }
+        });
+        Edges::new(&DynamicImage::ImageRgb8(image))
+    }
+
+    #[test]
+    fn snaps_within_the_distance() {
+        let edges = edges(&[(30, 40, 70, 60)]);
+        assert_eq!(edges.snap_x(36, 40, 60), Some(30));
+        assert_eq!(edges.snap_x(64, 40, 60), Some(70));
+        assert_eq!(edges.snap_y(45, 30, 70), Some(40));
+        assert_eq!(edges.snap_x(39, 40, 60), None);
+        assert_eq!(edges.snap_y(50, 30, 70), None);
+    }
+
+    #[test]
+    fn snaps_to_the_closest_edge() {
+        let edges = edges(&[(30, 20, 36, 80)]);
+        assert_eq!(edges.snap_x(34, 20, 80), Some(36));
+        assert_eq!(edges.snap_x(32, 20, 80), Some(30));
+        assert_eq!(edges.snap_x(33, 20, 80), Some(30));
+    }
+
+    #[test]
+    fn needs_half_of_the_selection_covered() {
+        let edges = edges(&[(30, 40, 70, 60)]);
+        assert_eq!(edges.snap_x(32, 30, 70), Some(30));
+        assert_eq!(edges.snap_x(32, 0, 100), None);
+        assert_eq!(edges.snap_x(32, 50, 50), None);
+    }
+
+    #[test]
+    fn ignores_short_edges_and_the_image_border() {
+        let edges = edges(&[(30, 40, 70, 45), (0, 0, 10, 100)]);
+        assert_eq!(edges.snap_x(30, 40, 45), None);
+        assert_eq!(edges.snap_y(40, 30, 70), Some(40));
+        assert_eq!(edges.snap_x(2, 0, 100), Some(10));
+    }
+}
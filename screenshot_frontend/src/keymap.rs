@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+use winit::event::{ModifiersState, MouseButton, VirtualKeyCode};
+
+/// Something the overlay does when a key or mouse button is pressed. Which of them
+/// apply depends on the mode the overlay is in.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Captures the selection, or the whole monitor without one.
+    Confirm,
+    /// Closes the overlay without capturing anything.
+    Cancel,
+    /// Captures the whole monitor.
+    FullScreen,
+    /// Puts the selection on the clipboard and keeps the overlay open.
+    Copy,
+    /// Saves the selection to the pictures directory instead of the clipboard.
+    SaveAs,
+    /// Takes back the last change to the selection, measurement or palette.
+    Undo,
+    /// Does what pressing or releasing the left button at the crosshair would.
+    Click,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Picks the handle moved by the arrow keys with Shift, backwards with Shift.
+    NextHandle,
+    Rectangle,
+    Ellipse,
+    Lasso,
+    PickColor,
+    Ruler,
+    Boxes,
+    AspectRatio,
+    FixedSize,
+    ColorFormat,
+    ContrastReport,
+    LargerSample,
+    SmallerSample,
+    /// Magnifies the loupe more.
+    ZoomIn,
+    ZoomOut,
+}
+
+impl fmt::Display for Action {
+    /// The name used in the config, like `full_screen`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, c) in format!("{:?}", self).chars().enumerate() {
+            if c.is_uppercase() && i > 0 {
+                write!(f, "_")?;
+            }
+            write!(f, "{}", c.to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of bindings to start from.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    /// hjkl to move, v to start a selection, y to copy, u to undo and q to quit.
+    Vim,
+}
+
+// Enter takes the full monitor until something is selected, then it takes the selection.
+const DEFAULT_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::Confirm, &["enter"]),
+    (Action::Cancel, &["escape", "mouse_right"]),
+    (Action::FullScreen, &["shift+enter"]),
+    (Action::Copy, &["ctrl+c"]),
+    (Action::SaveAs, &["ctrl+s"]),
+    (Action::Undo, &["ctrl+z"]),
+    (Action::Click, &["space"]),
+    (Action::Left, &["left"]),
+    (Action::Right, &["right"]),
+    (Action::Up, &["up"]),
+    (Action::Down, &["down"]),
+    (Action::NextHandle, &["tab"]),
+    (Action::Rectangle, &["r"]),
+    (Action::Ellipse, &["e"]),
+    (Action::Lasso, &["l"]),
+    (Action::PickColor, &["c"]),
+    (Action::Ruler, &["m"]),
+    (Action::Boxes, &["b"]),
+    (Action::AspectRatio, &["a"]),
+    (Action::FixedSize, &["s"]),
+    (Action::ColorFormat, &["f"]),
+    (Action::ContrastReport, &["w"]),
+    (Action::LargerSample, &["equals", "plus", "numpadadd"]),
+    (Action::SmallerSample, &["minus", "numpadsubtract"]),
+    (Action::ZoomIn, &["z"]),
+    (Action::ZoomOut, &["shift+z"]),
+];
+
+// Replaces the default bindings of these actions.
+const VIM_BINDINGS: &[(Action, &[&str])] = &[
+    (Action::Cancel, &["escape", "q", "mouse_right"]),
+    (Action::Copy, &["y"]),
+    (Action::Undo, &["u"]),
+    (Action::Click, &["v", "space"]),
+    (Action::Left, &["h", "left"]),
+    (Action::Down, &["j", "down"]),
+    (Action::Up, &["k", "up"]),
+    (Action::Right, &["l", "right"]),
+    (Action::Lasso, &["o"]),
+];
+
+/// The `[keymap]` section of the config: a preset, and keys for single actions that
+/// replace the ones from the preset.
+#[derive(Default)]
+pub struct KeymapConfig {
+    pub preset: Preset,
+    pub bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl KeymapConfig {
+    /// Reads the section entry by entry, so every mistake is reported with its key in
+    /// `errors`. An action with an invalid key keeps the ones from the preset.
+    pub fn parse(value: toml::Value, errors: &mut Vec<String>) -> Self {
+        let mut config = Self::default();
+        let table = match value {
+            toml::Value::Table(table) => table,
+            _ => {
+                errors.push("[keymap]: expected a table".to_owned());
+                return config;
+            }
+        };
+        for (name, value) in table {
+            if name == "preset" {
+                match value.try_into() {
+                    Ok(preset) => config.preset = preset,
+                    Err(_) => errors.push("keymap.preset: expected \"default\" or \"vim\"".to_owned()),
+                }
+                continue;
+            }
+            let action: Action = match toml::Value::String(name.clone()).try_into() {
+                Ok(action) => action,
+                Err(_) => {
+                    errors.push(format!("keymap.{}: unknown action", name));
+                    continue;
+                }
+            };
+            let keys = match value {
+                toml::Value::Array(keys) => keys,
+                _ => {
+                    errors.push(format!("keymap.{}: expected a list of keys", name));
+                    continue;
+                }
+            };
+            let mut bindings = vec![];
+            let mut valid = true;
+            for key in keys {
+                let binding = match key.as_str() {
+                    Some(key) => key.parse(),
+                    None => Err(format!("expected a key name, not {}", key)),
+                };
+                match binding {
+                    Ok(binding) => bindings.push(binding),
+                    Err(e) => {
+                        errors.push(format!("keymap.{}: {}", name, e));
+                        valid = false;
+                    }
+                }
+            }
+            if valid {
+                config.bindings.insert(action, bindings);
+            }
+        }
+        config
+    }
+}
+
+/// What is pressed for a binding.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// A key or mouse button with the modifiers that have to be held for it, written like
+/// `ctrl+shift+a` or `mouse_right`. Other modifiers may be held as well.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Binding {
+    input: Input,
+    modifiers: ModifiersState,
+}
+
+impl std::str::FromStr for Binding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        // "plus" is the way to write the key itself.
+        let name = parts.pop().unwrap().to_lowercase();
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            modifiers |= match part.to_lowercase().as_str() {
+                "shift" => ModifiersState::SHIFT,
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "alt" => ModifiersState::ALT,
+                "super" | "logo" => ModifiersState::LOGO,
+                _ => return Err(format!("unknown modifier '{}' in '{}', expected shift, ctrl, alt or super", part, s)),
+            };
+        }
+        let input = KEYS.iter().find(|(key, _)| *key == name).map(|(_, key)| Input::Key(*key))
+            .or_else(|| BUTTONS.iter().find(|(button, _)| *button == name).map(|(_, button)| Input::Mouse(*button)))
+            .ok_or_else(|| format!("unknown key '{}' in '{}'", name, s))?;
+        Ok(Binding { input, modifiers })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [(ModifiersState::LOGO, "super"), (ModifiersState::CTRL, "ctrl"), (ModifiersState::ALT, "alt"), (ModifiersState::SHIFT, "shift")] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        let name = match self.input {
+            Input::Key(key) => KEYS.iter().find(|(_, k)| *k == key).map(|(name, _)| *name),
+            Input::Mouse(button) => BUTTONS.iter().find(|(_, b)| *b == button).map(|(name, _)| *name),
+        };
+        write!(f, "{}", name.unwrap_or("?"))
+    }
+}
+
+/// Which keys and mouse buttons do what in the overlay.
+pub struct Keymap {
+    bindings: Vec<(Binding, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(&KeymapConfig::default()).unwrap()
+    }
+}
+
+impl Keymap {
+    /// The bindings of the preset with the ones from the config on top. Fails with a line
+    /// for each problem when a binding is ambiguous or the overlay couldn't be left.
+    pub fn new(config: &KeymapConfig) -> Result<Self, String> {
+        let preset = match config.preset {
+            Preset::Default => &[][..],
+            Preset::Vim => VIM_BINDINGS,
+        };
+        let mut bindings: Vec<(Binding, Action)> = vec![];
+        for (action, keys) in DEFAULT_BINDINGS {
+            let keys: Vec<Binding> = match config.bindings.get(action) {
+                Some(keys) => keys.clone(),
+                None => {
+                    let keys = preset.iter().find(|(preset, _)| preset == action).map_or(*keys, |(_, keys)| *keys);
+                    keys.iter().map(|key| key.parse().unwrap()).collect()
+                },
+            };
+            bindings.extend(keys.into_iter().map(|binding| (binding, *action)));
+        }
+
+        let mut errors = vec![];
+        for (i, (binding, action)) in bindings.iter().enumerate() {
+            if binding.input == Input::Mouse(MouseButton::Left) {
+                errors.push(format!("{} can't be bound to {}, the left button selects", binding, action));
+            }
+            // Compared as parsed, so `ctrl+c` and `control+c` are the same binding.
+            if let Some((_, other)) = bindings[..i].iter().find(|(other, other_action)| other == binding && other_action != action) {
+                errors.push(format!("{} is bound to both {} and {}", binding, other, action));
+            }
+        }
+        if !bindings.iter().any(|(_, action)| *action == Action::Cancel) {
+            errors.push("cancel needs a key, otherwise the overlay can't be closed".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(Self { bindings })
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    /// What pressing `input` does. When several bindings fit, the one needing the most
+    /// modifiers wins, so `shift+enter` goes before `enter`.
+    pub fn action(&self, input: Input, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings.iter()
+            .filter(|(binding, _)| binding.input == input && modifiers.contains(binding.modifiers))
+            .max_by_key(|(binding, _)| binding.modifiers.bits().count_ones())
+            .map(|(_, action)| *action)
+    }
+}
+
+const BUTTONS: &[(&str, MouseButton)] = &[
+    ("mouse_left", MouseButton::Left), ("mouse_right", MouseButton::Right), ("mouse_middle", MouseButton::Middle),
+];
+
+// The names keys are written with in the config.
+const KEYS: &[(&str, VirtualKeyCode)] = &[
+    ("a", VirtualKeyCode::A), ("b", VirtualKeyCode::B), ("c", VirtualKeyCode::C), ("d", VirtualKeyCode::D),
+    ("e", VirtualKeyCode::E), ("f", VirtualKeyCode::F), ("g", VirtualKeyCode::G), ("h", VirtualKeyCode::H),
+    ("i", VirtualKeyCode::I), ("j", VirtualKeyCode::J), ("k", VirtualKeyCode::K), ("l", VirtualKeyCode::L),
+    ("m", VirtualKeyCode::M), ("n", VirtualKeyCode::N), ("o", VirtualKeyCode::O), ("p", VirtualKeyCode::P),
+    ("q", VirtualKeyCode::Q), ("r", VirtualKeyCode::R), ("s", VirtualKeyCode::S), ("t", VirtualKeyCode::T),
+    ("u", VirtualKeyCode::U), ("v", VirtualKeyCode::V), ("w", VirtualKeyCode::W), ("x", VirtualKeyCode::X),
+    ("y", VirtualKeyCode::Y), ("z", VirtualKeyCode::Z),
+    ("0", VirtualKeyCode::Key0), ("1", VirtualKeyCode::Key1), ("2", VirtualKeyCode::Key2), ("3", VirtualKeyCode::Key3),
+    ("4", VirtualKeyCode::Key4), ("5", VirtualKeyCode::Key5), ("6", VirtualKeyCode::Key6), ("7", VirtualKeyCode::Key7),
+    ("8", VirtualKeyCode::Key8), ("9", VirtualKeyCode::Key9),
+    ("f1", VirtualKeyCode::F1), ("f2", VirtualKeyCode::F2), ("f3", VirtualKeyCode::F3), ("f4", VirtualKeyCode::F4),
+    ("f5", VirtualKeyCode::F5), ("f6", VirtualKeyCode::F6), ("f7", VirtualKeyCode::F7), ("f8", VirtualKeyCode::F8),
+    ("f9", VirtualKeyCode::F9), ("f10", VirtualKeyCode::F10), ("f11", VirtualKeyCode::F11), ("f12", VirtualKeyCode::F12),
+    ("escape", VirtualKeyCode::Escape), ("enter", VirtualKeyCode::Return), ("space", VirtualKeyCode::Space),
+    ("tab", VirtualKeyCode::Tab), ("backspace", VirtualKeyCode::Back), ("delete", VirtualKeyCode::Delete),
+    ("insert", VirtualKeyCode::Insert), ("home", VirtualKeyCode::Home), ("end", VirtualKeyCode::End),
+    ("pageup", VirtualKeyCode::PageUp), ("pagedown", VirtualKeyCode::PageDown),
+    ("left", VirtualKeyCode::Left), ("right", VirtualKeyCode::Right), ("up", VirtualKeyCode::Up), ("down", VirtualKeyCode::Down),
+    ("minus", VirtualKeyCode::Minus), ("equals", VirtualKeyCode::Equals), ("plus", VirtualKeyCode::Plus),
+    ("comma", VirtualKeyCode::Comma), ("period", VirtualKeyCode::Period), ("slash", VirtualKeyCode::Slash),
+    ("semicolon", VirtualKeyCode::Semicolon), ("apostrophe", VirtualKeyCode::Apostrophe),
+    ("bracketleft", VirtualKeyCode::LBracket), ("bracketright", VirtualKeyCode::RBracket),
+    ("numpadadd", VirtualKeyCode::NumpadAdd), ("numpadsubtract", VirtualKeyCode::NumpadSubtract),
+    ("numpadenter", VirtualKeyCode::NumpadEnter),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(config: &str) -> (KeymapConfig, Vec<String>) {
+        let mut errors = vec![];
+        let config = KeymapConfig::parse(toml::from_str(config).unwrap(), &mut errors);
+        (config, errors)
+    }
+
+    fn keymap(config: &str) -> Result<Keymap, String> {
+        let (config, errors) = parse(config);
+        assert!(errors.is_empty(), "{:?}", errors);
+        Keymap::new(&config)
+    }
+
+    #[test]
+    fn bindings_parse_with_modifiers() {
+        let binding: Binding = "Ctrl+Shift+A".parse().unwrap();
+        assert!(binding.input == Input::Key(VirtualKeyCode::A));
+        assert_eq!(binding.modifiers, ModifiersState::CTRL | ModifiersState::SHIFT);
+        assert_eq!(binding.to_string(), "ctrl+shift+a");
+        assert_eq!("control + mouse_middle".parse::<Binding>().unwrap().to_string(), "ctrl+mouse_middle");
+        assert_eq!("plus".parse::<Binding>().unwrap().to_string(), "plus");
+    }
+
+    #[test]
+    fn bindings_reject_unknown_names() {
+        assert!("hyper+a".parse::<Binding>().err().unwrap().contains("unknown modifier 'hyper'"));
+        assert!("ctrl+capslock".parse::<Binding>().err().unwrap().contains("unknown key 'capslock'"));
+    }
+
+    #[test]
+    fn presets_have_no_conflicts() {
+        let default = keymap("").unwrap();
+        assert_eq!(default.action(Input::Key(VirtualKeyCode::L), ModifiersState::empty()), Some(Action::Lasso));
+        let vim = keymap("preset = \"vim\"").unwrap();
+        assert_eq!(vim.action(Input::Key(VirtualKeyCode::L), ModifiersState::empty()), Some(Action::Right));
+        assert_eq!(vim.action(Input::Key(VirtualKeyCode::O), ModifiersState::empty()), Some(Action::Lasso));
+    }
+
+    #[test]
+    fn config_bindings_replace_the_preset() {
+        let keymap = keymap("preset = \"vim\"\nundo = [\"ctrl+z\"]").unwrap();
+        assert_eq!(keymap.action(Input::Key(VirtualKeyCode::Z), ModifiersState::CTRL), Some(Action::Undo));
+        assert_eq!(keymap.action(Input::Key(VirtualKeyCode::U), ModifiersState::empty()), None);
+    }
+
+    #[test]
+    fn duplicates_are_reported() {
+        let error = keymap("preset = \"vim\"\nruler = [\"h\"]").err().unwrap();
+        assert_eq!(error, "h is bound to both left and ruler");
+        // Binding a key twice to the same action is harmless.
+        assert!(keymap("copy = [\"y\", \"y\"]").is_ok());
+    }
+
+    #[test]
+    fn equivalent_bindings_conflict() {
+        let error = keymap("ruler = [\"Control+C\"]").err().unwrap();
+        assert_eq!(error, "ctrl+c is bound to both copy and ruler");
+    }
+
+    #[test]
+    fn invalid_entries_are_reported_with_their_key() {
+        let (config, errors) = parse("preset = \"emacs\"\ncopy = [\"ctrl+y\", \"hyper+c\"]\nundo = [\"ctrl+u\"]\nfly = [\"f\"]\nzoom_in = \"z\"");
+        let mut errors = errors;
+        errors.sort();
+        assert_eq!(errors, [
+            "keymap.copy: unknown modifier 'hyper' in 'hyper+c', expected shift, ctrl, alt or super",
+            "keymap.fly: unknown action",
+            "keymap.preset: expected \"default\" or \"vim\"",
+            "keymap.zoom_in: expected a list of keys",
+        ]);
+        // The valid entries still apply, the broken action keeps its preset keys.
+        assert!(config.bindings.contains_key(&Action::Undo));
+        assert!(!config.bindings.contains_key(&Action::Copy));
+    }
+
+    #[test]
+    fn the_overlay_can_always_be_left() {
+        let error = keymap("cancel = [\"mouse_left\"]").err().unwrap();
+        assert_eq!(error, "mouse_left can't be bound to cancel, the left button selects");
+        let error = keymap("cancel = []").err().unwrap();
+        assert_eq!(error, "cancel needs a key, otherwise the overlay can't be closed");
+    }
+
+    #[test]
+    fn the_most_specific_binding_wins() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(Input::Key(VirtualKeyCode::Return), ModifiersState::SHIFT), Some(Action::FullScreen));
+        assert_eq!(keymap.action(Input::Key(VirtualKeyCode::Return), ModifiersState::CTRL), Some(Action::Confirm));
+    }
+}
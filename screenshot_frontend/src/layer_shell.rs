@@ -0,0 +1,445 @@
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::process;
+use std::ffi::c_void;
+
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, WaylandHandle};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_compositor::WlCompositor, wl_keyboard::{self, WlKeyboard}, wl_output::{self, WlOutput},
+    wl_pointer::{self, WlPointer}, wl_registry::WlRegistry, wl_seat::{self, WlSeat}, wl_shm::WlShm, wl_surface::WlSurface};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle, WEnum};
+use wayland_cursor::CursorTheme;
+use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1}};
+use winit::{event::{ElementState, ModifiersState, MouseButton, VirtualKeyCode}, dpi::{PhysicalPosition, PhysicalSize}, window::CursorIcon};
+
+use crate::config::RenderConfig;
+use crate::overlay::{Host, Overlay};
+use crate::render::{self, Renderer};
+
+// Linux input event codes for the mouse buttons.
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+/// Shows the overlay on a wlr-layer-shell surface, which sits above all windows
+/// without relying on how the compositor handles fullscreen windows.
+///
+/// Gives the overlay back if this is not a Wayland session or the compositor
+/// lacks the layer shell or anything else the surface needs, so it can be shown
+/// in a normal window instead.
+pub fn run(monitor_position: PhysicalPosition<i32>, overlay: Overlay, render: RenderConfig) -> Result<(), Box<Overlay>> {
+    if env::var_os("WAYLAND_DISPLAY").is_none() {
+        return Err(Box::new(overlay));
+    }
+    let conn = match Connection::connect_to_env() {
+        Ok(conn) => conn,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    let (globals, mut queue) = match registry_queue_init::<Layer>(&conn) {
+        Ok(globals) => globals,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    let qh = queue.handle();
+    let layer_shell: ZwlrLayerShellV1 = match globals.bind(&qh, 1..=4, ()) {
+        Ok(layer_shell) => layer_shell,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    let compositor: WlCompositor = match globals.bind(&qh, 3..=4, ()) {
+        Ok(compositor) => compositor,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    let shm: WlShm = match globals.bind(&qh, 1..=1, ()) {
+        Ok(shm) => shm,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    // Without a seat there is no way to use the overlay.
+    let _seat: WlSeat = match globals.bind(&qh, 1..=5, ()) {
+        Ok(seat) => seat,
+        Err(_) => return Err(Box::new(overlay)),
+    };
+    let outputs: Vec<WlOutput> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == WlOutput::interface().name)
+            .map(|global| globals.registry().bind(global.name, global.version.min(2), &qh, ()))
+            .collect()
+    });
+
+    let surface = compositor.create_surface(&qh, ());
+    let mut layer = Layer {
+        host: LayerHost {
+            conn: conn.clone(),
+            layer_surface: None,
+            size: Cell::new(PhysicalSize::new(0, 0)),
+            exit: Cell::new(false),
+            pointer: RefCell::new(None),
+            cursor: Cell::new(None),
+            cursor_surface: compositor.create_surface(&qh, ()),
+            cursor_theme: RefCell::new(CursorTheme::load(&conn, shm, 24).ok()),
+        },
+        overlay,
+        state: None,
+        surface,
+        outputs: outputs.into_iter().map(|output| (output, OutputInfo::default())).collect(),
+        scale: 1,
+        render,
+        dirty: true,
+    };
+    if queue.roundtrip(&mut layer).is_err() {
+        return Err(Box::new(layer.overlay));
+    }
+
+    // The backend passes the position of the captured output in compositor coordinates, as
+    // wl_output reports it. Should none match, the compositor picks the output.
+    let position = (monitor_position.x, monitor_position.y);
+    let output = layer.outputs.iter().find(|(_, info)| info.position == position);
+    layer.scale = output.map(|(_, info)| info.scale).unwrap_or(1);
+    let layer_surface = layer_shell.get_layer_surface(
+        &layer.surface, output.map(|(output, _)| output), zwlr_layer_shell_v1::Layer::Overlay, "sss".to_owned(), &qh, (),
+    );
+    layer_surface.set_anchor(zwlr_layer_surface_v1::Anchor::all());
+    layer_surface.set_exclusive_zone(-1);
+    layer_surface.set_keyboard_interactivity(zwlr_layer_surface_v1::KeyboardInteractivity::Exclusive);
+    layer.surface.set_buffer_scale(layer.scale);
+    layer.surface.commit();
+    layer.host.layer_surface = Some(layer_surface);
+
+    while !layer.host.exit.get() {
+        // A frame that couldn't be drawn is tried again without waiting for input.
+        let dispatched = if layer.dirty { queue.dispatch_pending(&mut layer) } else { queue.blocking_dispatch(&mut layer) };
+        if let Err(e) = dispatched {
+            eprintln!("Lost the connection to the compositor: {}", e);
+            break;
+        }
+        // Once the overlay is done its surface may already be gone.
+        if layer.dirty && !layer.host.exit.get() {
+            layer.redraw();
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct OutputInfo {
+    position: (i32, i32),
+    scale: i32,
+}
+
+struct LayerHost {
+    conn: Connection,
+    layer_surface: Option<ZwlrLayerSurfaceV1>,
+    size: Cell<PhysicalSize<u32>>,
+    exit: Cell<bool>,
+    /// The pointer on the surface and the serial of its enter event.
+    pointer: RefCell<Option<(WlPointer, u32)>>,
+    cursor: Cell<Option<CursorIcon>>,
+    cursor_surface: WlSurface,
+    cursor_theme: RefCell<Option<CursorTheme>>,
+}
+
+impl Host for LayerHost {
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size.get()
+    }
+
+    fn set_cursor(&self, icon: CursorIcon) {
+        if self.cursor.get() == Some(icon) {
+            return;
+        }
+        let pointer = self.pointer.borrow();
+        let (pointer, serial) = match pointer.as_ref() {
+            Some(pointer) => pointer,
+            None => return,
+        };
+        let mut theme = self.cursor_theme.borrow_mut();
+        let theme = match theme.as_mut() {
+            Some(theme) => theme,
+            None => return,
+        };
+        // Themes name their cursors after CSS or after the old X cursor font.
+        let names: &[&str] = match icon {
+            CursorIcon::Move => &["move", "fleur"],
+            CursorIcon::Grabbing => &["grabbing", "fleur"],
+            CursorIcon::NwResize => &["nw-resize", "top_left_corner"],
+            CursorIcon::NResize => &["n-resize", "top_side"],
+            CursorIcon::NeResize => &["ne-resize", "top_right_corner"],
+            CursorIcon::EResize => &["e-resize", "right_side"],
+            CursorIcon::SeResize => &["se-resize", "bottom_right_corner"],
+            CursorIcon::SResize => &["s-resize", "bottom_side"],
+            CursorIcon::SwResize => &["sw-resize", "bottom_left_corner"],
+            CursorIcon::WResize => &["w-resize", "left_side"],
+            _ => &["crosshair"],
+        };
+        let name = names.iter().copied().find(|name| theme.get_cursor(name).is_some());
+        if let Some(cursor) = name.and_then(|name| theme.get_cursor(name)) {
+            let image = &cursor[0];
+            let (x, y) = image.hotspot();
+            self.cursor_surface.attach(Some(image), 0, 0);
+            if self.cursor_surface.version() >= 4 {
+                self.cursor_surface.damage_buffer(0, 0, i32::MAX, i32::MAX);
+            } else {
+                self.cursor_surface.damage(0, 0, i32::MAX, i32::MAX);
+            }
+            self.cursor_surface.commit();
+            pointer.set_cursor(*serial, Some(&self.cursor_surface), x as i32, y as i32);
+            self.cursor.set(Some(icon));
+        }
+    }
+
+    fn hide(&self) {
+        if let Some(layer_surface) = &self.layer_surface {
+            layer_surface.destroy();
+        }
+        self.conn.flush().ok();
+    }
+
+    fn exit(&self) {
+        self.exit.set(true);
+    }
+}
+
+struct Layer {
+    host: LayerHost,
+    overlay: Overlay,
+    state: Option<Box<dyn Renderer>>,
+    surface: WlSurface,
+    outputs: Vec<(WlOutput, OutputInfo)>,
+    scale: i32,
+    render: RenderConfig,
+    dirty: bool,
+}
+
+impl Layer {
+    fn redraw(&mut self) {
+        self.dirty = false;
+        // Drawn once the surface is configured.
+        let state = match &mut self.state {
+            Some(state) => state,
+            None => return,
+        };
+        self.overlay.update(&self.host, state.as_mut());
+        match state.render() {
+            Ok(_) => {}
+
+            // Drawn again once the surface is back.
+            Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+                state.resize(self.host.size());
+                self.dirty = true;
+            },
+
+            Err(wgpu::SurfaceError::OutOfMemory) => self.host.exit(),
+
+            Err(e) => eprintln!("Err: {:?}", e),
+        }
+    }
+
+    fn configure(&mut self, width: u32, height: u32) {
+        let size = PhysicalSize::new(width * self.scale as u32, height * self.scale as u32);
+        self.host.size.set(size);
+        match &mut self.state {
+            Some(state) => state.resize(size),
+            None => {
+                let handle = RawSurface {
+                    display: self.host.conn.backend().display_ptr() as *mut c_void,
+                    surface: self.surface.id().as_ptr() as *mut c_void,
+                };
+                let state = render::renderer(&handle, size, self.overlay.image(), self.overlay.theme(), self.render)
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    });
+                self.state = Some(state);
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+struct RawSurface {
+    display: *mut c_void,
+    surface: *mut c_void,
+}
+
+unsafe impl HasRawWindowHandle for RawSurface {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = WaylandHandle::empty();
+        handle.display = self.display;
+        handle.surface = self.surface;
+        RawWindowHandle::Wayland(handle)
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Layer {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlOutput, ()> for Layer {
+    fn event(layer: &mut Self, output: &WlOutput, event: wl_output::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let info = match layer.outputs.iter_mut().find(|(o, _)| o == output) {
+            Some((_, info)) => info,
+            None => return,
+        };
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => info.position = (x, y),
+            wl_output::Event::Scale { factor } => info.scale = factor,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for Layer {
+    fn event(_: &mut Self, seat: &WlSeat, event: wl_seat::Event, _: &(), _: &Connection, qh: &QueueHandle<Self>) {
+        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(capabilities) } = event {
+            if capabilities.contains(wl_seat::Capability::Pointer) {
+                seat.get_pointer(qh, ());
+            }
+            if capabilities.contains(wl_seat::Capability::Keyboard) {
+                seat.get_keyboard(qh, ());
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for Layer {
+    fn event(layer: &mut Self, layer_surface: &ZwlrLayerSurfaceV1, event: zwlr_layer_surface_v1::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, width, height } => {
+                layer_surface.ack_configure(serial);
+                layer.configure(width, height);
+            },
+            zwlr_layer_surface_v1::Event::Closed => layer.host.exit(),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for Layer {
+    fn event(layer: &mut Self, pointer: &WlPointer, event: wl_pointer::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        let scale = layer.scale as f64;
+        match event {
+            wl_pointer::Event::Enter { serial, surface_x, surface_y, .. } => {
+                // The cursor has to be set again on every enter.
+                *layer.host.pointer.borrow_mut() = Some((pointer.clone(), serial));
+                layer.host.cursor.set(None);
+                layer.overlay.cursor_moved(&layer.host, PhysicalPosition::new((surface_x * scale) as u32, (surface_y * scale) as u32));
+            },
+            wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+                layer.overlay.cursor_moved(&layer.host, PhysicalPosition::new((surface_x * scale) as u32, (surface_y * scale) as u32));
+            },
+            wl_pointer::Event::Button { button, state: WEnum::Value(state), .. } => {
+                let button = match button {
+                    BTN_LEFT => MouseButton::Left,
+                    BTN_RIGHT => MouseButton::Right,
+                    BTN_MIDDLE => MouseButton::Middle,
+                    other => MouseButton::Other(other as u16),
+                };
+                let state = match state {
+                    wl_pointer::ButtonState::Pressed => ElementState::Pressed,
+                    _ => ElementState::Released,
+                };
+                layer.overlay.mouse_input(&layer.host, state, button);
+            },
+            _ => return,
+        }
+        layer.dirty = true;
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for Layer {
+    fn event(layer: &mut Self, _: &WlKeyboard, event: wl_keyboard::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {
+        match event {
+            wl_keyboard::Event::Key { key, state: WEnum::Value(state), .. } => {
+                let state = match state {
+                    wl_keyboard::KeyState::Pressed => ElementState::Pressed,
+                    _ => ElementState::Released,
+                };
+                if let Some(key) = virtual_keycode(key) {
+                    layer.overlay.keyboard_input(&layer.host, state, key);
+                    layer.dirty = true;
+                }
+            },
+            wl_keyboard::Event::Modifiers { mods_depressed, mods_latched, mods_locked, .. } => {
+                // Bits of the standard modifiers in the usual keymaps (Shift, Control, Mod1, Mod4).
+                let mods = mods_depressed | mods_latched | mods_locked;
+                let mut modifiers = ModifiersState::empty();
+                modifiers.set(ModifiersState::SHIFT, mods & 1 << 0 != 0);
+                modifiers.set(ModifiersState::CTRL, mods & 1 << 2 != 0);
+                modifiers.set(ModifiersState::ALT, mods & 1 << 3 != 0);
+                modifiers.set(ModifiersState::LOGO, mods & 1 << 6 != 0);
+                layer.overlay.modifiers_changed(modifiers);
+                layer.dirty = true;
+            },
+            _ => {}
+        }
+    }
+}
+
+macro_rules! ignore_events {
+    ($($interface:ty),*) => {
+        $(
+            impl Dispatch<$interface, ()> for Layer {
+                fn event(_: &mut Self, _: &$interface, _: <$interface as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+            }
+        )*
+    };
+}
+
+ignore_events!(WlCompositor, WlSurface, WlShm, ZwlrLayerShellV1);
+
+// Translates a Linux key code into the key it has on a US layout, which is
+// what the overlay's shortcuts are defined for.
+fn virtual_keycode(key: u32) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const LETTERS: [(u32, VirtualKeyCode); 26] = [
+        (30, A), (48, B), (46, C), (32, D), (18, E), (33, F), (34, G), (35, H), (23, I), (36, J), (37, K), (38, L), (50, M),
+        (49, N), (24, O), (25, P), (16, Q), (19, R), (31, S), (20, T), (22, U), (47, V), (17, W), (45, X), (21, Y), (44, Z),
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0];
+    const FUNCTION: [VirtualKeyCode; 10] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10];
+
+    if let Some((_, letter)) = LETTERS.iter().find(|(code, _)| *code == key) {
+        return Some(*letter);
+    }
+    Some(match key {
+        1 => Escape,
+        2..=11 => DIGITS[key as usize - 2],
+        12 => Minus,
+        13 => Equals,
+        14 => Back,
+        15 => Tab,
+        26 => LBracket,
+        27 => RBracket,
+        28 => Return,
+        29 => LControl,
+        39 => Semicolon,
+        40 => Apostrophe,
+        41 => Grave,
+        42 => LShift,
+        43 => Backslash,
+        51 => Comma,
+        52 => Period,
+        53 => Slash,
+        54 => RShift,
+        56 => LAlt,
+        57 => Space,
+        59..=68 => FUNCTION[key as usize - 59],
+        74 => NumpadSubtract,
+        78 => NumpadAdd,
+        96 => NumpadEnter,
+        97 => RControl,
+        100 => RAlt,
+        102 => Home,
+        103 => Up,
+        104 => PageUp,
+        105 => Left,
+        106 => Right,
+        107 => End,
+        108 => Down,
+        109 => PageDown,
+        110 => Insert,
+        111 => Delete,
+        125 => LWin,
+        126 => RWin,
+        _ => return None,
+    })
+}
@@ -0,0 +1,40 @@
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+
+// Whole pixels are looked up directly so they stay sharp squares, with a line
+// between them to make them easy to count.
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_diffuse));
+    let texel = in.tex_coords * size;
+    let grid = fwidth(texel);
+    if (texel.x < 0.0 || texel.y < 0.0 || texel.x >= size.x || texel.y >= size.y) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let color = textureLoad(t_diffuse, vec2<i32>(floor(texel)), 0);
+    let within = fract(texel);
+    if (within.x < grid.x || within.y < grid.y) {
+        return vec4<f32>(color.rgb * 0.6, 1.0);
+    }
+    return color;
+}
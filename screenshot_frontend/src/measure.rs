@@ -0,0 +1,67 @@
+use winit::dpi::PhysicalPosition;
+
+use crate::selection::Selection;
+use crate::shape::Polygon;
+
+/// Length, offsets and angle of the line from `start` to `end`. The angle is counter
+/// clockwise from pointing right, like on a protractor.
+pub fn ruler_label(start: PhysicalPosition<u32>, end: PhysicalPosition<u32>) -> String {
+    let (dx, dy) = (end.x as i32 - start.x as i32, end.y as i32 - start.y as i32);
+    let length = ((dx * dx + dy * dy) as f32).sqrt();
+    // The y axis points down on screen.
+    let angle = (-dy as f32).atan2(dx as f32).to_degrees().rem_euclid(360.0);
+    format!("{:.1} px  dx {}  dy {}  {:.1}°", length, dx, dy, angle)
+}
+
+/// Where a line from `start` to `end` ends when it is kept horizontal or vertical,
+/// whichever is closer.
+pub fn straighten(start: PhysicalPosition<u32>, end: PhysicalPosition<u32>) -> PhysicalPosition<u32> {
+    if start.x.abs_diff(end.x) >= start.y.abs_diff(end.y) {
+        PhysicalPosition::new(end.x, start.y)
+    } else {
+        PhysicalPosition::new(start.x, end.y)
+    }
+}
+
+/// A line `width` pixels wide as a polygon, with square ends sticking out `cap` pixels
+/// to either side. Nothing for a line without length.
+pub fn line(from: (f32, f32), to: (f32, f32), width: f32, cap: f32) -> Vec<Polygon> {
+    let length = ((to.0 - from.0).powi(2) + (to.1 - from.1).powi(2)).sqrt();
+    if length == 0.0 {
+        return vec![];
+    }
+    let direction = ((to.0 - from.0) / length, (to.1 - from.1) / length);
+    let normal = (-direction.1, direction.0);
+    let quad = |a: (f32, f32), b: (f32, f32), half: f32| vec![
+        (a.0 + normal.0 * half, a.1 + normal.1 * half),
+        (b.0 + normal.0 * half, b.1 + normal.1 * half),
+        (b.0 - normal.0 * half, b.1 - normal.1 * half),
+        (a.0 - normal.0 * half, a.1 - normal.1 * half),
+    ];
+    let along = |point: (f32, f32), distance: f32| (point.0 + direction.0 * distance, point.1 + direction.1 * distance);
+    vec![
+        quad(from, to, width / 2.0),
+        quad(along(from, -width / 2.0), along(from, width / 2.0), cap),
+        quad(along(to, -width / 2.0), along(to, width / 2.0), cap),
+    ]
+}
+
+/// The empty space between two boxes that don't overlap sideways or vertically, as
+/// the two ends of a line across it.
+pub fn gaps(a: &Selection, b: &Selection) -> Vec<((i32, i32), (i32, i32))> {
+    let mut gaps = vec![];
+    // Through the middle of where the boxes overlap, or between them where they don't.
+    let y = (a.top.max(b.top) + a.bottom.min(b.bottom)) / 2;
+    let x = (a.left.max(b.left) + a.right.min(b.right)) / 2;
+    if b.left >= a.right {
+        gaps.push(((a.right, y), (b.left, y)));
+    } else if a.left >= b.right {
+        gaps.push(((b.right, y), (a.left, y)));
+    }
+    if b.top >= a.bottom {
+        gaps.push(((x, a.bottom), (x, b.top)));
+    } else if a.top >= b.bottom {
+        gaps.push(((x, b.bottom), (x, a.top)));
+    }
+    gaps
+}
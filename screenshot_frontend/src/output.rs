@@ -0,0 +1,34 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use dirs::home_dir;
+
+// Results are reported to the backend on stdout, one per line.
+
+/// Asks the backend to put the PNG at `path` on the clipboard.
+pub fn copy_image(path: &Path) {
+    println!("image {}", path.display());
+}
+
+/// Asks the backend to put the window `id` on the clipboard, or the PNG at `path` when it
+/// can't capture the window by itself.
+pub fn copy_window(id: u32, path: &Path) {
+    println!("window {} {}", id, path.display());
+}
+
+/// Asks the backend to put `text` on the clipboard.
+pub fn copy_text(text: &str) {
+    println!("text {}", text);
+}
+
+/// Where the selected part of the capture is written before handing it to the backend.
+pub fn tmp_path() -> PathBuf {
+    home_dir().unwrap().join(".sss/tmp.png")
+}
+
+/// A new, unused file name in the user's pictures directory.
+pub fn save_path() -> PathBuf {
+    let dir = dirs::picture_dir().unwrap_or_else(|| home_dir().unwrap());
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    dir.join(format!("sss-{}.png", millis))
+}
@@ -0,0 +1,930 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use image::{DynamicImage, RgbaImage};
+use winit::{event::{ElementState, ModifiersState, VirtualKeyCode, MouseButton}, dpi::{PhysicalPosition, PhysicalSize, LogicalPosition}, window::CursorIcon};
+
+use crate::color::{self, Format};
+use crate::config::{Config, Dimensions, HandleStyle, ThemeConfig};
+use crate::edges::Edges;
+use crate::keymap::{Action, Input, Keymap};
+use crate::measure;
+use crate::output;
+use crate::render::{ColorVertex, Renderer, Vertex};
+use crate::selection::{self, Handle, Selection};
+use crate::shape::{self, Shape};
+use crate::text;
+
+/// The surface the overlay is shown on, either a winit window or a layer-shell surface.
+pub trait Host {
+    fn size(&self) -> PhysicalSize<u32>;
+    fn set_cursor(&self, icon: CursorIcon);
+    /// Takes the overlay off the screen right away, before the result is written.
+    fn hide(&self);
+    fn exit(&self);
+}
+
+// Presses that move less than this are clicks rather than drags.
+const DRAG_THRESHOLD: u32 = 4;
+// Two clicks closer together than this confirm the selection.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+// Size of the squares drawn on the handles, in pixels.
+const HANDLE_SIZE: f32 = 8.0;
+// How far the arrow keys move things while Ctrl is held.
+const LARGE_STEP: i32 = 10;
+// The loupe shows this many pixels in each direction around the cursor,
+const LOUPE_RADIUS: i32 = 10;
+// each of them this big at first,
+const LOUPE_ZOOM: i32 = 8;
+// this far from the cursor.
+const LOUPE_OFFSET: i32 = 24;
+// Zooming the loupe halves or doubles its pixels within these sizes.
+const LOUPE_ZOOM_RANGE: (i32, i32) = (2, 16);
+// The largest area a picked colour is averaged over.
+const MAX_SAMPLE_SIZE: u32 = 15;
+// How many picked colours the palette keeps,
+const PALETTE_SIZE: usize = 8;
+// shown as squares this big in the top left corner.
+const SWATCH_SIZE: i32 = 24;
+
+/// A window on the captured monitor, in image pixels.
+#[derive(Clone, Copy)]
+pub struct Rect {
+    /// The X window, which the backend can capture again on its own.
+    pub id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    fn contains(&self, position: PhysicalPosition<u32>) -> bool {
+        let (x, y) = (position.x as i32, position.y as i32);
+        x >= self.x && x < self.x + self.width as i32 && y >= self.y && y < self.y + self.height as i32
+    }
+}
+
+impl std::str::FromStr for Rect {
+    type Err = String;
+
+    /// Parses `id,x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid window '{}'", s);
+        let values: Vec<&str> = s.split(',').collect();
+        if let [id, x, y, width, height] = values[..] {
+            Ok(Rect {
+                id: id.parse().map_err(|_| invalid())?,
+                x: x.parse().map_err(|_| invalid())?,
+                y: y.parse().map_err(|_| invalid())?,
+                width: width.parse().map_err(|_| invalid())?,
+                height: height.parse().map_err(|_| invalid())?,
+            })
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+// What the left button is doing while it is held.
+enum Drag {
+    /// Selecting a new area, `path` is where the cursor went for lassos.
+    Create { start: PhysicalPosition<u32>, path: Vec<PhysicalPosition<u32>> },
+    Move { start: PhysicalPosition<u32>, original: Selection },
+    Resize { handle: Handle, original: Selection },
+    /// Measuring a line or a box.
+    Measure { start: PhysicalPosition<u32> },
+}
+
+// What clicks and drags do.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Select,
+    /// Copying colours.
+    Pick,
+    /// Measuring the length and angle of lines.
+    Ruler,
+    /// Measuring boxes and the gaps between them.
+    Boxes,
+}
+
+/// The selection on top of the captured monitor.
+///
+/// Dragging selects a rectangle, an ellipse or a lasso (switched with R, E and L),
+/// clicking selects the window under the cursor. The selection can then be moved
+/// and resized with its handles until it is confirmed with Enter or a double click.
+/// All of it can be done with the keyboard too. Dragged edges snap to edges in the
+/// image unless Alt is held. Shift or a preset from the config locks the aspect ratio,
+/// a fixed size preset gives a selection that only needs to be put in place.
+///
+/// C switches to picking colours instead, clicks then copy the colour under the cursor.
+/// The last two colours picked are checked for their contrast. M and B switch to
+/// measuring lines and boxes.
+pub struct Overlay {
+    image: DynamicImage,
+    /// Windows on the monitor at capture time, topmost first.
+    windows: Vec<Rect>,
+    edges: Edges,
+    shape: Shape,
+    selection: Option<Selection>,
+    drag: Option<Drag>,
+    /// The cursor, or the crosshair moved with the arrow keys.
+    mouse_position: PhysicalPosition<u32>,
+    last_click: Option<(Instant, PhysicalPosition<u32>)>,
+    modifiers: ModifiersState,
+    keymap: Keymap,
+    /// How big the pixels in the loupe are.
+    loupe_zoom: i32,
+    /// Earlier selections to go back to, oldest first.
+    undo: Vec<Option<Selection>>,
+    /// Whether the keyboard was used since the mouse last moved.
+    keyboard: bool,
+    /// The handle Shift and the arrow keys move.
+    handle: Handle,
+    ratios: Vec<Dimensions>,
+    /// The aspect ratio from `ratios` the selection is locked to.
+    ratio: Option<usize>,
+    sizes: Vec<Dimensions>,
+    /// The size from `sizes` new selections have.
+    fixed_size: Option<usize>,
+    mode: Mode,
+    format: Format,
+    /// Width and height of the area a picked colour is averaged over, always odd.
+    sample_size: u32,
+    /// The colours picked so far, least recently used first.
+    palette: Vec<[u8; 3]>,
+    /// Corners of the measured boxes or ends of the measured line.
+    measurements: Vec<(PhysicalPosition<u32>, PhysicalPosition<u32>)>,
+    /// Colours of the dimmed screen, the selection's border and its handles.
+    theme: ThemeConfig,
+}
+
+impl Overlay {
+    pub fn new(image: DynamicImage, windows: Vec<Rect>, config: &Config) -> Self {
+        Self {
+            edges: Edges::new(&image),
+            image,
+            windows,
+            shape: Shape::Rectangle,
+            selection: None,
+            drag: None,
+            mouse_position: PhysicalPosition::new(0, 0),
+            last_click: None,
+            modifiers: ModifiersState::empty(),
+            keymap: Keymap::new(&config.keymap).unwrap_or_else(|errors| {
+                eprintln!("Invalid keymap, using the default one:\n{}", errors);
+                Keymap::default()
+            }),
+            loupe_zoom: LOUPE_ZOOM,
+            undo: vec![],
+            keyboard: false,
+            handle: Handle::BottomRight,
+            ratios: config.selection.ratios.clone(),
+            ratio: None,
+            sizes: config.selection.sizes.clone(),
+            fixed_size: None,
+            mode: Mode::Select,
+            format: config.picker.format,
+            sample_size: (config.picker.size.clamp(1, MAX_SAMPLE_SIZE) - 1) | 1,
+            palette: vec![],
+            measurements: vec![],
+            theme: config.theme.clone(),
+        }
+    }
+
+    pub fn image(&self) -> &DynamicImage {
+        &self.image
+    }
+
+    pub fn theme(&self) -> &ThemeConfig {
+        &self.theme
+    }
+
+    /// Closing the window from outside, with Alt+F4 or the window manager, is a cancel.
+    pub fn close_requested(&mut self, host: &dyn Host) {
+        self.perform(host, Action::Cancel);
+    }
+
+    pub fn modifiers_changed(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    pub fn keyboard_input(&mut self, host: &dyn Host, state: ElementState, key: VirtualKeyCode) {
+        if state != ElementState::Pressed {
+            return;
+        }
+        if let Some(action) = self.keymap.action(Input::Key(key), self.modifiers) {
+            self.perform(host, action);
+        }
+    }
+
+    fn perform(&mut self, host: &dyn Host, action: Action) {
+        let step = if self.modifiers.ctrl() { LARGE_STEP } else { 1 };
+        let direction = match action {
+            Action::Left => Some((-step, 0)),
+            Action::Right => Some((step, 0)),
+            Action::Up => Some((0, -step)),
+            Action::Down => Some((0, step)),
+            _ => None,
+        };
+        match (direction, self.mode) {
+            (Some((dx, dy)), Mode::Select) => return self.nudge(dx, dy),
+            (Some((dx, dy)), _) => return self.move_crosshair(dx, dy),
+            (None, _) => {},
+        }
+        match action {
+            // Nothing is written, so whatever is on the clipboard stays there.
+            Action::Cancel => host.exit(),
+            Action::FullScreen => {
+                self.crop(host, None);
+                host.exit();
+            },
+            Action::ZoomIn => self.loupe_zoom = (self.loupe_zoom * 2).min(LOUPE_ZOOM_RANGE.1),
+            Action::ZoomOut => self.loupe_zoom = (self.loupe_zoom / 2).max(LOUPE_ZOOM_RANGE.0),
+            Action::Undo => match self.mode {
+                Mode::Select => {
+                    if let Some(selection) = self.undo.pop() {
+                        self.selection = selection;
+                        self.drag = None;
+                    }
+                },
+                Mode::Pick => {
+                    self.palette.pop();
+                },
+                Mode::Ruler | Mode::Boxes => {
+                    self.measurements.pop();
+                },
+            },
+            Action::PickColor if self.drag.is_none() => self.toggle_mode(Mode::Pick),
+            Action::Ruler if self.drag.is_none() => self.toggle_mode(Mode::Ruler),
+            Action::Boxes if self.drag.is_none() => self.toggle_mode(Mode::Boxes),
+            _ if self.mode == Mode::Select => self.select_action(host, action),
+            _ => self.tool_action(host, action),
+        }
+    }
+
+    fn select_action(&mut self, host: &dyn Host, action: Action) {
+        match action {
+            // Confirming in the middle of a keyboard selection finishes it first.
+            Action::Confirm => {
+                if let Some(Drag::Create { .. }) = self.drag {
+                    self.mouse_input(host, ElementState::Released, MouseButton::Left);
+                }
+                if self.drag.is_none() {
+                    self.crop(host, self.selection.as_ref());
+                    host.exit();
+                }
+            },
+            // The overlay stays open, so after a failure it can still be tried again.
+            Action::Copy if self.drag.is_none() => self.copy(self.selection.as_ref()),
+            Action::SaveAs if self.drag.is_none() => {
+                host.hide();
+                self.save(self.selection.as_ref(), &output::save_path());
+                host.exit();
+            },
+            // Clicking goes through the same steps as the mouse: start a selection at the
+            // crosshair, then finish it where the crosshair went.
+            Action::Click => match (&self.drag, &self.selection) {
+                (Some(Drag::Create { .. }), _) => self.mouse_input(host, ElementState::Released, MouseButton::Left),
+                (None, None) => {
+                    // A fixed size selection is there right away.
+                    match self.fixed_box(self.mouse_position) {
+                        Some(fixed) => self.set_selection(Some(fixed)),
+                        None => self.drag = Some(Drag::Create { start: self.mouse_position, path: vec![self.mouse_position] }),
+                    }
+                    self.keyboard = true;
+                },
+                _ => {}
+            },
+            Action::NextHandle if self.selection.is_some() => {
+                let index = Handle::ALL.iter().position(|handle| *handle == self.handle).unwrap();
+                let next = if self.modifiers.shift() { index + Handle::ALL.len() - 1 } else { index + 1 };
+                self.handle = Handle::ALL[next % Handle::ALL.len()];
+                self.keyboard = true;
+            },
+            // Switching in the middle of a drag would throw away the lasso path.
+            Action::Rectangle if self.drag.is_none() => self.shape = Shape::Rectangle,
+            Action::Ellipse if self.drag.is_none() => self.shape = Shape::Ellipse,
+            Action::Lasso if self.drag.is_none() => self.shape = Shape::Lasso,
+            // Each press picks the next preset, after the last one there is none.
+            Action::AspectRatio => {
+                self.ratio = next_preset(self.ratio, self.ratios.len());
+                self.fixed_size = None;
+            },
+            Action::FixedSize if self.drag.is_none() => {
+                self.fixed_size = next_preset(self.fixed_size, self.sizes.len());
+                self.ratio = None;
+            },
+            _ => {}
+        }
+    }
+
+    // The key of the current mode goes back to selecting.
+    fn toggle_mode(&mut self, mode: Mode) {
+        self.mode = if self.mode == mode { Mode::Select } else { mode };
+        self.measurements.clear();
+    }
+
+    // While picking colours or measuring, confirming clicks at the crosshair, which starts
+    // or ends a measurement.
+    fn tool_action(&mut self, host: &dyn Host, action: Action) {
+        match action {
+            Action::Confirm | Action::Click => {
+                let state = if self.drag.is_some() { ElementState::Released } else { ElementState::Pressed };
+                self.mouse_input(host, state, MouseButton::Left);
+            },
+            _ if self.mode == Mode::Pick => self.picker_action(action),
+            _ => {}
+        }
+    }
+
+    fn picker_action(&mut self, action: Action) {
+        match action {
+            Action::ColorFormat => self.format = self.format.next(),
+            Action::ContrastReport => {
+                if let Some(report) = self.contrast_report() {
+                    output::copy_text(&report);
+                }
+            },
+            Action::LargerSample => self.sample_size = (self.sample_size + 2).min(MAX_SAMPLE_SIZE),
+            Action::SmallerSample => self.sample_size = self.sample_size.saturating_sub(2).max(1),
+            _ => {}
+        }
+    }
+
+    // Copies the colour at `position` and adds it to the palette.
+    fn pick(&mut self, position: PhysicalPosition<u32>) {
+        self.copy_color(color::average(&self.image, position.x, position.y, self.sample_size));
+    }
+
+    // Copies `color` and puts it at the end of the palette, where the contrast is checked.
+    fn copy_color(&mut self, color: [u8; 3]) {
+        output::copy_text(&self.format.format(color));
+        self.palette.retain(|picked| *picked != color);
+        self.palette.push(color);
+        if self.palette.len() > PALETTE_SIZE {
+            self.palette.remove(0);
+        }
+    }
+
+    // The two colours checked for their contrast.
+    fn contrast_pair(&self) -> Option<([u8; 3], [u8; 3])> {
+        match self.palette[..] {
+            [.., first, second] => Some((first, second)),
+            _ => None,
+        }
+    }
+
+    // The contrast of the last two colours on one line, to paste into a review.
+    fn contrast_report(&self) -> Option<String> {
+        let (first, second) = self.contrast_pair()?;
+        Some(format!("{} on {}  {}", self.format.format(second), self.format.format(first), color::contrast_summary(first, second)))
+    }
+
+    // Left and top edge of the palette's `index`th swatch.
+    fn swatch_position(index: usize) -> (i32, i32) {
+        let gap = HANDLE_SIZE as i32;
+        (gap + index as i32 * (SWATCH_SIZE + gap), gap)
+    }
+
+    fn swatch_at(&self, position: PhysicalPosition<u32>) -> Option<usize> {
+        let (x, y) = (position.x as i32, position.y as i32);
+        (0..self.palette.len()).find(|index| {
+            let (left, top) = Self::swatch_position(*index);
+            x >= left && x < left + SWATCH_SIZE && y >= top && y < top + SWATCH_SIZE
+        })
+    }
+
+    // Arrow keys move the selection, or with Shift the edges of the handle picked with Tab.
+    // Without a selection they move the crosshair instead of the mouse.
+    fn nudge(&mut self, dx: i32, dy: i32) {
+        self.keyboard = true;
+        let (width, height) = (self.image.width(), self.image.height());
+        match (&self.drag, &self.selection) {
+            (None, Some(selection)) if self.modifiers.shift() => {
+                let (x, y) = selection.handle_position(self.handle);
+                let position = PhysicalPosition::new((x + dx).clamp(0, width as i32) as u32, (y + dy).clamp(0, height as i32) as u32);
+                let resized = selection.resized(self.handle, position);
+                if resized.width() > 0 && resized.height() > 0 {
+                    self.set_selection(Some(resized));
+                }
+            },
+            (None, Some(selection)) => self.set_selection(Some(selection.moved(dx, dy, width, height))),
+            (Some(Drag::Move { .. }), _) | (Some(Drag::Resize { .. }), _) => {},
+            _ => self.move_crosshair(dx, dy),
+        }
+    }
+
+    fn move_crosshair(&mut self, dx: i32, dy: i32) {
+        self.keyboard = true;
+        let position = PhysicalPosition::new(
+            (self.mouse_position.x as i32 + dx).clamp(0, self.image.width() as i32 - 1) as u32,
+            (self.mouse_position.y as i32 + dy).clamp(0, self.image.height() as i32 - 1) as u32,
+        );
+        self.mouse_position = position;
+        if let Some(Drag::Create { path, .. }) = &mut self.drag {
+            if self.shape == Shape::Lasso {
+                path.push(position);
+            }
+        }
+    }
+
+    pub fn cursor_moved(&mut self, host: &dyn Host, position: PhysicalPosition<u32>) {
+        self.mouse_position = position;
+        self.keyboard = false;
+        if let Some(Drag::Create { path, .. }) = &mut self.drag {
+            if self.shape == Shape::Lasso {
+                path.push(position);
+            }
+        }
+        host.set_cursor(self.cursor());
+    }
+
+    pub fn mouse_input(&mut self, host: &dyn Host, state: ElementState, button: MouseButton) {
+        // The left button always selects, the others do what the keymap says.
+        if button != MouseButton::Left {
+            if let Some(action) = self.keymap.action(Input::Mouse(button), self.modifiers).filter(|_| state == ElementState::Pressed) {
+                self.perform(host, action);
+            }
+            return;
+        }
+        let position = self.mouse_position;
+        match (self.mode, state) {
+            (Mode::Select, _) => {},
+            (Mode::Pick, ElementState::Pressed) => {
+                match self.swatch_at(position) {
+                    // Picking a swatch copies it again, in the current format.
+                    Some(index) => self.copy_color(self.palette[index]),
+                    None => self.pick(position),
+                }
+                return;
+            },
+            (Mode::Pick, ElementState::Released) => return,
+            (_, ElementState::Pressed) => {
+                self.drag = Some(Drag::Measure { start: position });
+                return;
+            },
+            (_, ElementState::Released) => {
+                if let Some(Drag::Measure { start }) = self.drag.take() {
+                    // A ruler measures one line, boxes are measured in pairs.
+                    if self.mode == Mode::Ruler || self.measurements.len() == 2 {
+                        self.measurements.clear();
+                    }
+                    self.measurements.push((start, self.measure_end(start)));
+                }
+                return;
+            },
+        }
+        match state {
+            ElementState::Pressed => {
+                let double_click = matches!(self.last_click, Some((time, last)) if time.elapsed() < DOUBLE_CLICK &&
+                    last.x.abs_diff(position.x) <= DRAG_THRESHOLD && last.y.abs_diff(position.y) <= DRAG_THRESHOLD);
+                self.last_click = Some((Instant::now(), position));
+                self.drag = Some(match &self.selection {
+                    Some(selection) if double_click && selection.contains(position) => {
+                        self.crop(host, Some(selection));
+                        host.exit();
+                        return;
+                    },
+                    Some(selection) => match selection.handle_at(position) {
+                        Some(handle) => Drag::Resize { handle, original: selection.clone() },
+                        None if selection.contains(position) => Drag::Move { start: position, original: selection.clone() },
+                        None => self.create(position),
+                    },
+                    None => self.create(position),
+                });
+            },
+            ElementState::Released => {
+                if let Some(Drag::Create { start, .. }) = &self.drag {
+                    if !self.dragged(*start) {
+                        // A click selects the window under it, or nothing.
+                        self.set_selection(self.window_at(*start));
+                        self.drag = None;
+                        return;
+                    }
+                }
+                if self.drag.is_some() {
+                    self.set_selection(self.current().filter(|selection| selection.width() > 0 && selection.height() > 0));
+                    self.drag = None;
+                }
+            },
+        }
+    }
+
+    // Replaces the selection, keeping the old one for undoing.
+    fn set_selection(&mut self, selection: Option<Selection>) {
+        let previous = std::mem::replace(&mut self.selection, selection);
+        self.undo.push(previous);
+    }
+
+    // Starts a new selection at `position`. With a fixed size it is there right away and
+    // the drag only moves it.
+    fn create(&mut self, position: PhysicalPosition<u32>) -> Drag {
+        match self.fixed_box(position) {
+            Some(fixed) => {
+                self.set_selection(Some(fixed.clone()));
+                Drag::Move { start: position, original: fixed }
+            },
+            None => Drag::Create { start: position, path: vec![position] },
+        }
+    }
+
+    // A selection of the fixed size centred on `position`, as far as it fits on the image.
+    fn fixed_box(&self, position: PhysicalPosition<u32>) -> Option<Selection> {
+        let size = self.sizes[self.fixed_size?];
+        let (width, height) = (size.width.min(self.image.width()), size.height.min(self.image.height()));
+        let left = position.x.saturating_sub(width / 2).min(self.image.width() - width);
+        let top = position.y.saturating_sub(height / 2).min(self.image.height() - height);
+        Some(Selection::new(self.shape_or_rectangle(), PhysicalPosition::new(left, top), PhysicalPosition::new(left + width, top + height)))
+    }
+
+    // Lassos have no size to lock.
+    fn shape_or_rectangle(&self) -> Shape {
+        if self.shape == Shape::Lasso { Shape::Rectangle } else { self.shape }
+    }
+
+    // The width to height ratio the selection keeps, 1:1 while Shift is held.
+    fn locked_ratio(&self) -> Option<(u32, u32)> {
+        if self.modifiers.shift() {
+            return Some((1, 1));
+        }
+        self.ratio.map(|index| (self.ratios[index].width, self.ratios[index].height))
+    }
+
+    /// Brings the render state up to date before a redraw.
+    pub fn update(&self, host: &dyn Host, state: &mut dyn Renderer) {
+        let size = host.size();
+        let to_clip = |(x, y): (f32, f32)| LogicalPosition::new((x / size.width as f32) * 2.0 - 1.0, 1.0 - (y / size.height as f32) * 2.0);
+        let mut marks = vec![];
+        let mut borders = vec![];
+        let mut handles = vec![];
+        let mut swatches = vec![];
+        let mut labels = vec![];
+        // Selections are left alone while picking colours or measuring.
+        match self.current().filter(|_| self.mode == Mode::Select) {
+            Some(selection) => {
+                let polygon = selection.outline();
+                let triangles: Vec<LogicalPosition<f32>> = shape::triangles(&polygon).into_iter().map(to_clip).collect();
+                state.update(&triangles);
+                let width = self.theme.border_width;
+                if width > 0.0 {
+                    for (i, &from) in polygon.iter().enumerate() {
+                        let to = polygon[(i + 1) % polygon.len()];
+                        // Square ends half as long as the line is wide fill in the corners.
+                        for line in measure::line(from, to, width, width / 2.0) {
+                            borders.extend(shape::triangles(&line));
+                        }
+                    }
+                }
+                labels.extend(self.dimensions_label(&selection, size));
+                // Handles only make sense once there is something to adjust.
+                if self.selection.is_some() && !matches!(self.drag, Some(Drag::Create { .. })) {
+                    for handle in Handle::ALL {
+                        let (x, y) = selection.handle_position(handle);
+                        // The one the keyboard moves stands out.
+                        let half = if self.keyboard && handle == self.handle { HANDLE_SIZE } else { HANDLE_SIZE / 2.0 };
+                        let (x, y) = (x as f32, y as f32);
+                        let polygon = match self.theme.handles {
+                            HandleStyle::Square => shape::rectangle_f32(x - half, y - half, x + half, y + half),
+                            HandleStyle::Circle => shape::ellipse_f32(x - half, y - half, x + half, y + half),
+                            HandleStyle::None => continue,
+                        };
+                        handles.extend(shape::triangles(&polygon));
+                    }
+                }
+            },
+            None => state.clear_selection(),
+        }
+        // There is no mouse cursor to show where the keyboard is.
+        if self.keyboard && (self.selection.is_none() || self.mode != Mode::Select) {
+            let (x, y) = (self.mouse_position.x as f32, self.mouse_position.y as f32);
+            marks.extend(shape::triangles(&shape::rectangle_f32(0.0, y, size.width as f32, y + 1.0)));
+            marks.extend(shape::triangles(&shape::rectangle_f32(x, 0.0, x + 1.0, size.height as f32)));
+        }
+        let loupe = self.update_loupe(state, &mut marks, size);
+        match self.mode {
+            Mode::Select => {},
+            Mode::Pick => self.update_picker(loupe, &mut marks, &mut swatches, &mut labels, size),
+            Mode::Ruler | Mode::Boxes => self.update_measurements(&mut marks, &mut labels, size),
+        }
+        let white = [1.0; 4];
+        let border_color = color::linear(self.theme.border_color.0);
+        let handle_color = color::linear(self.theme.handle_color.0);
+        let marks: Vec<ColorVertex> = borders.into_iter().map(|point| ColorVertex::new(to_clip(point), border_color))
+            .chain(handles.into_iter().map(|point| ColorVertex::new(to_clip(point), handle_color)))
+            .chain(marks.into_iter().map(|point| ColorVertex::new(to_clip(point), white)))
+            .chain(swatches.into_iter().map(|(point, color)| ColorVertex::new(to_clip(point), color::linear(color))))
+            .collect();
+        state.update_marks(&marks);
+        state.update_text(&labels);
+    }
+
+    // Where a measurement started at `start` ends, Shift keeps lines horizontal or vertical.
+    fn measure_end(&self, start: PhysicalPosition<u32>) -> PhysicalPosition<u32> {
+        if self.mode == Mode::Ruler && self.modifiers.shift() {
+            measure::straighten(start, self.mouse_position)
+        } else {
+            self.mouse_position
+        }
+    }
+
+    // Measured lines with their length and angle, or boxes with their size and the gaps between them.
+    fn update_measurements(&self, marks: &mut Vec<(f32, f32)>, labels: &mut Vec<Vertex>, size: PhysicalSize<u32>) {
+        let mut measurements = self.measurements.clone();
+        if let Some(Drag::Measure { start }) = self.drag {
+            if self.mode == Mode::Ruler || measurements.len() == 2 {
+                measurements.clear();
+            }
+            measurements.push((start, self.measure_end(start)));
+        }
+        let gap = HANDLE_SIZE as i32;
+        if self.mode == Mode::Ruler {
+            for (start, end) in measurements {
+                // Through the middle of the pixels at the ends.
+                let center = |point: PhysicalPosition<u32>| (point.x as f32 + 0.5, point.y as f32 + 0.5);
+                for line in measure::line(center(start), center(end), 1.0, HANDLE_SIZE / 2.0) {
+                    marks.extend(shape::triangles(&line));
+                }
+                labels.extend(label(&measure::ruler_label(start, end), end.x as i32 + gap, end.y as i32 + gap, size));
+            }
+            return;
+        }
+
+        let boxes: Vec<Selection> = measurements.iter().map(|(start, end)| Selection::new(Shape::Rectangle, *start, *end)).collect();
+        for measured in &boxes {
+            outline(marks, measured.left, measured.top, measured.right, measured.bottom);
+            labels.extend(self.dimensions_label(measured, size));
+        }
+        if let [a, b] = &boxes[..] {
+            for (from, to) in measure::gaps(a, b) {
+                let point = |(x, y): (i32, i32)| (x as f32, y as f32);
+                for line in measure::line(point(from), point(to), 1.0, HANDLE_SIZE / 2.0) {
+                    marks.extend(shape::triangles(&line));
+                }
+                let text = format!("{} px", (to.0 - from.0) + (to.1 - from.1));
+                let (x, y) = ((from.0 + to.0) / 2, (from.1 + to.1) / 2);
+                labels.extend(label(&text, x + gap, y + gap, size));
+            }
+        }
+    }
+
+    // The colour under the cursor written out below the loupe, after a swatch of it, and the palette.
+    fn update_picker(&self, loupe: (i32, i32, i32), marks: &mut Vec<(f32, f32)>, swatches: &mut Vec<((f32, f32), [u8; 3])>, labels: &mut Vec<Vertex>, size: PhysicalSize<u32>) {
+        let color = color::average(&self.image, self.mouse_position.x, self.mouse_position.y, self.sample_size);
+        let mut label = self.format.format(color);
+        if self.sample_size > 1 {
+            label = format!("{}  {} × {}", label, self.sample_size, self.sample_size);
+        }
+        let (left, top, side) = loupe;
+        let label_size = text::size(&label);
+        let height = label_size.height as i32;
+        let gap = HANDLE_SIZE as i32;
+        let y = if top + side + gap + height <= size.height as i32 { top + side + gap } else { top - gap - height };
+        let x = left.clamp(0, (size.width as i32 - height - label_size.width as i32).max(0));
+        swatches.extend(shape::triangles(&shape::rectangle_f32(x as f32, y as f32, (x + height) as f32, (y + height) as f32)).into_iter().map(|point| (point, color)));
+        labels.extend(text::vertices(&label, x + height, y, size));
+
+        for (index, color) in self.palette.iter().enumerate() {
+            let (left, top) = Self::swatch_position(index);
+            outline(marks, left, top, left + SWATCH_SIZE, top + SWATCH_SIZE);
+            let (left, top) = (left as f32, top as f32);
+            let swatch = shape::rectangle_f32(left, top, left + SWATCH_SIZE as f32, top + SWATCH_SIZE as f32);
+            swatches.extend(shape::triangles(&swatch).into_iter().map(|point| (point, *color)));
+        }
+        if let Some((first, second)) = self.contrast_pair() {
+            let (left, top) = Self::swatch_position(0);
+            labels.extend(text::vertices(&color::contrast_summary(first, second), left, top + SWATCH_SIZE + gap, size));
+        }
+    }
+
+    // Size and position of the selection, above its top left corner or below it near the top of the screen.
+    fn dimensions_label(&self, selection: &Selection, size: PhysicalSize<u32>) -> Vec<Vertex> {
+        let mut label = format!("{} × {}  {}, {}", selection.width(), selection.height(), selection.left, selection.top);
+        if let Some(ratio) = self.ratio.filter(|_| self.mode == Mode::Select) {
+            label = format!("{}  {}:{}", label, self.ratios[ratio].width, self.ratios[ratio].height);
+        }
+        let label_size = text::size(&label);
+        let gap = HANDLE_SIZE as i32;
+        let y = if selection.top - gap >= label_size.height as i32 { selection.top - gap - label_size.height as i32 } else { selection.bottom + gap };
+        self::label(&label, selection.left, y, size)
+    }
+
+    // Puts the loupe below and to the right of the cursor, or on the other side where
+    // it would leave the screen, and outlines it and the pixels under the cursor.
+    // Returns its left and top edge and its size.
+    fn update_loupe(&self, state: &mut dyn Renderer, marks: &mut Vec<(f32, f32)>, size: PhysicalSize<u32>) -> (i32, i32, i32) {
+        let (x, y) = (self.mouse_position.x as i32, self.mouse_position.y as i32);
+        let zoom = self.loupe_zoom;
+        let side = (2 * LOUPE_RADIUS + 1) * zoom;
+        let left = if x + LOUPE_OFFSET + side > size.width as i32 { x - LOUPE_OFFSET - side } else { x + LOUPE_OFFSET };
+        let top = if y + LOUPE_OFFSET + side > size.height as i32 { y - LOUPE_OFFSET - side } else { y + LOUPE_OFFSET };
+
+        let to_clip = |x: i32, y: i32| LogicalPosition::new((x as f32 / size.width as f32) * 2.0 - 1.0, 1.0 - (y as f32 / size.height as f32) * 2.0);
+        let to_tex = |x: i32, y: i32| LogicalPosition::new(x as f32 / self.image.width() as f32, y as f32 / self.image.height() as f32);
+        state.update_loupe(
+            to_clip(left, top), to_clip(left + side, top + side),
+            to_tex(x - LOUPE_RADIUS, y - LOUPE_RADIUS), to_tex(x + LOUPE_RADIUS + 1, y + LOUPE_RADIUS + 1),
+        );
+
+        // While picking colours that is the whole area they are averaged over.
+        let sample_size = if self.mode == Mode::Pick { self.sample_size as i32 } else { 1 };
+        let center = (left + (LOUPE_RADIUS - sample_size / 2) * zoom, top + (LOUPE_RADIUS - sample_size / 2) * zoom);
+        outline(marks, left, top, left + side, top + side);
+        outline(marks, center.0, center.1, center.0 + sample_size * zoom, center.1 + sample_size * zoom);
+        (left, top, side)
+    }
+
+    fn dragged(&self, start: PhysicalPosition<u32>) -> bool {
+        start.x.abs_diff(self.mouse_position.x) > DRAG_THRESHOLD || start.y.abs_diff(self.mouse_position.y) > DRAG_THRESHOLD
+    }
+
+    fn window_at(&self, position: PhysicalPosition<u32>) -> Option<Selection> {
+        let window = self.windows.iter().find(|window| window.contains(position))?;
+        Some(self.window_selection(window))
+    }
+
+    // The part of `window` that is on the image.
+    fn window_selection(&self, window: &Rect) -> Selection {
+        let clamp = |x: i32, y: i32| PhysicalPosition::new(
+            x.clamp(0, self.image.width() as i32) as u32,
+            y.clamp(0, self.image.height() as i32) as u32,
+        );
+        Selection::new(Shape::Rectangle, clamp(window.x, window.y), clamp(window.x + window.width as i32, window.y + window.height as i32))
+    }
+
+    // The window `selection` was picked from, as long as it wasn't changed since.
+    fn picked_window(&self, selection: &Selection) -> Option<&Rect> {
+        if selection.shape != Shape::Rectangle {
+            return None;
+        }
+        self.windows.iter().find(|window| {
+            let picked = self.window_selection(window);
+            (picked.left, picked.top, picked.right, picked.bottom) == (selection.left, selection.top, selection.right, selection.bottom)
+        })
+    }
+
+    // What is selected right now, including a drag in progress. Without a selection the
+    // window under the cursor is highlighted.
+    fn current(&self) -> Option<Selection> {
+        let position = self.mouse_position;
+        match &self.drag {
+            Some(Drag::Create { start, path }) if self.dragged(*start) => Some(match (self.shape, self.locked_ratio()) {
+                (Shape::Lasso, _) => Selection::lasso(path),
+                (shape, Some(ratio)) => Selection::new(shape, *start, selection::lock_ratio(*start, position, ratio, self.image_size())),
+                (shape, None) => self.snapped(Selection::new(shape, *start, position), None),
+            }),
+            Some(Drag::Create { start, .. }) => self.window_at(*start).or_else(|| self.selection.clone()),
+            Some(Drag::Move { start, original }) => {
+                let moved = original.moved(
+                    position.x as i32 - start.x as i32, position.y as i32 - start.y as i32, self.image.width(), self.image.height(),
+                );
+                Some(self.snapped_move(moved))
+            },
+            Some(Drag::Resize { handle, original }) => Some(match self.locked_ratio() {
+                Some(ratio) if original.shape != Shape::Lasso => original.resized_locked(*handle, position, ratio, self.image_size()),
+                _ => self.snapped(original.resized(*handle, position), Some(original)),
+            }),
+            Some(Drag::Measure { .. }) => None,
+            None => self.selection.clone().or_else(|| self.fixed_box(position)).or_else(|| self.window_at(position)),
+        }
+    }
+
+    fn image_size(&self) -> PhysicalSize<u32> {
+        PhysicalSize::new(self.image.width(), self.image.height())
+    }
+
+    // Puts the edges of `selection` that moved away from `original`, or all of them, onto
+    // nearby edges in the image. Lassos keep following the cursor.
+    fn snapped(&self, selection: Selection, original: Option<&Selection>) -> Selection {
+        if self.modifiers.alt() || selection.shape == Shape::Lasso {
+            return selection;
+        }
+        let moved = |edge: fn(&Selection) -> i32| original.is_none_or(|original| edge(original) != edge(&selection));
+        let mut snapped = selection.clone();
+        if moved(|s| s.left) {
+            snapped.left = self.edges.snap_x(selection.left, selection.top, selection.bottom).unwrap_or(selection.left);
+        }
+        if moved(|s| s.right) {
+            snapped.right = self.edges.snap_x(selection.right, selection.top, selection.bottom).unwrap_or(selection.right);
+        }
+        if moved(|s| s.top) {
+            snapped.top = self.edges.snap_y(selection.top, selection.left, selection.right).unwrap_or(selection.top);
+        }
+        if moved(|s| s.bottom) {
+            snapped.bottom = self.edges.snap_y(selection.bottom, selection.left, selection.right).unwrap_or(selection.bottom);
+        }
+        // Snapping both edges onto the same line would leave nothing selected.
+        if snapped.width() == 0 || snapped.height() == 0 || snapped.left > snapped.right || snapped.top > snapped.bottom {
+            return selection;
+        }
+        snapped
+    }
+
+    // Moves `selection` so one of its sides lies on a nearby edge in the image, keeping its size.
+    fn snapped_move(&self, selection: Selection) -> Selection {
+        if self.modifiers.alt() {
+            return selection;
+        }
+        let dx = self.edges.snap_x(selection.left, selection.top, selection.bottom).map(|x| x - selection.left)
+            .or_else(|| self.edges.snap_x(selection.right, selection.top, selection.bottom).map(|x| x - selection.right))
+            .unwrap_or(0);
+        let dy = self.edges.snap_y(selection.top, selection.left, selection.right).map(|y| y - selection.top)
+            .or_else(|| self.edges.snap_y(selection.bottom, selection.left, selection.right).map(|y| y - selection.bottom))
+            .unwrap_or(0);
+        selection.moved(dx, dy, self.image.width(), self.image.height())
+    }
+
+    fn cursor(&self) -> CursorIcon {
+        if self.mode == Mode::Pick {
+            return if self.swatch_at(self.mouse_position).is_some() { CursorIcon::Hand } else { CursorIcon::Crosshair };
+        }
+        if self.mode != Mode::Select {
+            return CursorIcon::Crosshair;
+        }
+        match (&self.drag, &self.selection) {
+            (Some(Drag::Move { .. }), _) => CursorIcon::Grabbing,
+            (Some(Drag::Resize { handle, .. }), _) => handle.cursor(),
+            (Some(Drag::Create { .. }), _) | (Some(Drag::Measure { .. }), _) => CursorIcon::Crosshair,
+            (None, Some(selection)) => match selection.handle_at(self.mouse_position) {
+                Some(handle) => handle.cursor(),
+                None if selection.contains(self.mouse_position) => CursorIcon::Move,
+                None => CursorIcon::Crosshair,
+            },
+            (None, None) => CursorIcon::Crosshair,
+        }
+    }
+
+    // Writes the selected part of the image, all of it without a selection.
+    fn crop(&self, host: &dyn Host, selection: Option<&Selection>) {
+        host.hide();
+        self.copy(selection);
+    }
+
+    // Hands the selection to the backend. A picked window is captured again on its own there,
+    // with whatever covers it left out, the crop is the fallback for that.
+    fn copy(&self, selection: Option<&Selection>) {
+        let path = output::tmp_path();
+        if !self.save(selection, &path) {
+            return;
+        }
+        match selection.and_then(|selection| self.picked_window(selection)) {
+            Some(window) => output::copy_window(window.id, &path),
+            None => output::copy_image(&path),
+        }
+    }
+
+    // Writes the selected part of the image to `path` as a PNG. A failure, such as a full
+    // disk, is reported rather than taking the overlay down with the screen still grabbed.
+    fn save(&self, selection: Option<&Selection>, path: &Path) -> bool {
+        match self.cropped(selection).save_with_format(path, image::ImageFormat::Png) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("Could not save {}: {}", path.display(), e);
+                false
+            }
+        }
+    }
+
+    // The selected part of the image, all of it without a selection.
+    fn cropped(&self, selection: Option<&Selection>) -> RgbaImage {
+        let mut image = self.image.to_rgba8();
+        if let Some(selection) = selection {
+            let x = selection.left.clamp(0, image.width() as i32) as u32;
+            let y = selection.top.clamp(0, image.height() as i32) as u32;
+            let width = (selection.right.clamp(0, image.width() as i32) as u32).saturating_sub(x);
+            let height = (selection.bottom.clamp(0, image.height() as i32) as u32).saturating_sub(y);
+            image = image::imageops::crop_imm(&image, x, y, width, height).to_image();
+            // Everything outside of an ellipse or lasso becomes transparent.
+            if selection.shape != Shape::Rectangle {
+                shape::mask(&mut image, &selection.outline(), PhysicalPosition::new(x, y));
+            }
+        }
+        image
+    }
+}
+
+// A one pixel line around the rectangle, just outside of it.
+fn outline(marks: &mut Vec<(f32, f32)>, left: i32, top: i32, right: i32, bottom: i32) {
+    let (left, top, right, bottom) = (left as f32 - 1.0, top as f32 - 1.0, right as f32, bottom as f32);
+    marks.extend(shape::triangles(&shape::rectangle_f32(left, top, right + 1.0, top + 1.0)));
+    marks.extend(shape::triangles(&shape::rectangle_f32(left, bottom, right + 1.0, bottom + 1.0)));
+    marks.extend(shape::triangles(&shape::rectangle_f32(left, top, left + 1.0, bottom)));
+    marks.extend(shape::triangles(&shape::rectangle_f32(right, top, right + 1.0, bottom)));
+}
+
+// `text` with its top left corner at `x`, `y`, moved back onto the screen where it would leave it.
+fn label(text: &str, x: i32, y: i32, size: PhysicalSize<u32>) -> Vec<Vertex> {
+    let label_size = text::size(text);
+    let x = x.clamp(0, (size.width as i32 - label_size.width as i32).max(0));
+    let y = y.clamp(0, (size.height as i32 - label_size.height as i32).max(0));
+    text::vertices(text, x, y, size)
+}
+
+// The preset after `current` out of `count`, or none after the last one.
+fn next_preset(current: Option<usize>, count: usize) -> Option<usize> {
+    match current {
+        None if count > 0 => Some(0),
+        Some(index) if index + 1 < count => Some(index + 1),
+        _ => None,
+    }
+}
@@ -0,0 +1,283 @@
+use std::fs;
+use std::path::PathBuf;
+
+use image::GenericImageView;
+use wgpu::include_wgsl;
+use wgpu::util::DeviceExt;
+use winit::{event_loop::{EventLoop, ControlFlow}, window::{WindowBuilder, Window}, event::{KeyboardInput, ElementState, VirtualKeyCode, MouseButton}, dpi::{PhysicalPosition, PhysicalSize, LogicalPosition}};
+
+use crate::config::Config;
+use crate::output;
+use crate::render::{self, Theme};
+
+const COLUMNS: u32 = 4;
+const CELL_WIDTH: u32 = 256;
+const CELL_HEIGHT: u32 = 160;
+const PADDING: u32 = 16;
+
+// Where a thumbnail is drawn, in window pixels.
+struct Cell {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Cell {
+    fn contains(&self, position: PhysicalPosition<u32>) -> bool {
+        position.x >= self.x && position.x < self.x + self.width && position.y >= self.y && position.y < self.y + self.height
+    }
+}
+
+/// Shows the given captures (newest first) as a grid of thumbnails.
+/// Left click puts a capture back on the clipboard, right click saves it to the pictures directory.
+pub fn run(paths: Vec<String>) {
+    // Entries deleted or damaged on disk are left out.
+    let (paths, images): (Vec<PathBuf>, Vec<image::DynamicImage>) = paths.into_iter()
+        .map(PathBuf::from)
+        .filter_map(|path| match image::open(&path) {
+            Ok(image) => Some((path, image.thumbnail(CELL_WIDTH, CELL_HEIGHT))),
+            Err(e) => {
+                eprintln!("Could not read {}: {}", path.display(), e);
+                None
+            }
+        })
+        .unzip();
+    if images.is_empty() {
+        eprintln!("The capture history is empty.");
+        return;
+    }
+
+    let columns = COLUMNS.min(images.len() as u32);
+    let rows = (images.len() as u32).div_ceil(columns);
+    let size = PhysicalSize::new(columns * (CELL_WIDTH + PADDING) + PADDING, rows * (CELL_HEIGHT + PADDING) + PADDING);
+
+    let cells: Vec<Cell> = images.iter().enumerate().map(|(index, image)| {
+        let (column, row) = (index as u32 % columns, index as u32 / columns);
+        let (width, height) = image.dimensions();
+        Cell {
+            x: PADDING + column * (CELL_WIDTH + PADDING) + (CELL_WIDTH - width) / 2,
+            y: PADDING + row * (CELL_HEIGHT + PADDING) + (CELL_HEIGHT - height) / 2,
+            width,
+            height,
+        }
+    }).collect();
+
+    let event_loop: EventLoop<()> = EventLoop::new();
+
+    let window = WindowBuilder::new()
+        .with_decorations(false)
+        .with_resizable(false)
+        .with_title("SSS History")
+        .with_inner_size(size)
+        .build(&event_loop).unwrap();
+    if let Some(monitor) = window.current_monitor().or_else(|| window.primary_monitor()) {
+        let x = monitor.position().x + (monitor.size().width as i32 - size.width as i32) / 2;
+        let y = monitor.position().y + (monitor.size().height as i32 - size.height as i32) / 2;
+        window.set_outer_position(PhysicalPosition::new(x, y));
+    }
+
+    let Some(mut picker) = pollster::block_on(Picker::new(&window, &images, &cells, &Config::load())) else {
+        eprintln!("The capture history needs a graphics adapter, none was found.");
+        return;
+    };
+    window.request_redraw();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+        match event {
+        winit::event::Event::WindowEvent {
+            ref event,
+            window_id,
+        } if window_id == window.id() => match event {
+            winit::event::WindowEvent::CloseRequested
+            | winit::event::WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                ..
+            } => {
+                *control_flow = ControlFlow::Exit;
+            },
+            winit::event::WindowEvent::Resized(physical_size) => {
+                picker.resize(*physical_size);
+                window.request_redraw();
+            }
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                let mouse_position = PhysicalPosition::new(position.x as u32, position.y as u32);
+                let hovered = cells.iter().position(|cell| cell.contains(mouse_position));
+                // Only the highlighted thumbnail depends on the cursor.
+                if hovered != picker.hovered {
+                    picker.hovered = hovered;
+                    window.request_redraw();
+                }
+            }
+            winit::event::WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => {
+                if let Some(index) = picker.hovered {
+                    match button {
+                        MouseButton::Left => {
+                            window.set_visible(false);
+                            output::copy_image(&paths[index]);
+                            *control_flow = ControlFlow::Exit;
+                        },
+                        MouseButton::Right => {
+                            window.set_visible(false);
+                            let path = output::save_path();
+                            if let Err(e) = fs::copy(&paths[index], &path) {
+                                eprintln!("Could not save {}: {}", path.display(), e);
+                            }
+                            *control_flow = ControlFlow::Exit;
+                        },
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        },
+        winit::event::Event::RedrawRequested(window_id) if window_id == window.id() => {
+            match picker.render() {
+                Ok(_) => {}
+
+                // Drawn again once the surface is back.
+                Err(wgpu::SurfaceError::Lost) => {
+                    picker.resize(picker.size);
+                    window.request_redraw();
+                },
+                Err(wgpu::SurfaceError::Outdated) => {
+                    picker.resize(window.inner_size());
+                    window.request_redraw();
+                },
+
+                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+
+                Err(e) => eprintln!("Err: {:?}", e),
+            }
+        },
+        _ => {}
+    }})
+}
+
+struct Picker {
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    render_pipeline: wgpu::RenderPipeline,
+    dimmed_render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    bind_groups: Vec<wgpu::BindGroup>,
+    theme_bind_group: wgpu::BindGroup,
+    hovered: Option<usize>,
+}
+
+impl Picker {
+    async fn new(window: &Window, images: &[image::DynamicImage], cells: &[Cell], settings: &Config) -> Option<Self> {
+        let size = window.inner_size();
+        let (surface, device, queue, config) = render::init_surface(window, size, settings.render.present_mode).await?;
+
+        let texture_bind_group_layout = render::texture_bind_group_layout(&device);
+        let bind_groups = images.iter()
+            .map(|image| render::create_texture_bind_group(&device, &queue, &texture_bind_group_layout, image))
+            .collect();
+
+        let uniform_bind_group_layout = render::uniform_bind_group_layout(&device);
+        let uniform = Theme::new(&settings.theme);
+        let theme_bind_group = render::create_uniform_bind_group(&device, &uniform_bind_group_layout, bytemuck::bytes_of(&uniform));
+
+        // The hovered thumbnail is drawn like a selection, all others dimmed like the screen around it.
+        let shader = device.create_shader_module(&include_wgsl!("overlay.wgsl"));
+        let render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout], &shader, config.format, None);
+        let dimmed_shader = device.create_shader_module(&include_wgsl!("shader.wgsl"));
+        let dimmed_render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout, &uniform_bind_group_layout], &dimmed_shader, config.format, None);
+
+        let to_clip = |x: u32, y: u32| LogicalPosition::new(
+            x as f32 / size.width as f32 * 2.0 - 1.0,
+            1.0 - y as f32 / size.height as f32 * 2.0,
+        );
+        let vertices: Vec<render::Vertex> = cells.iter()
+            .flat_map(|cell| render::quad(
+                to_clip(cell.x, cell.y), to_clip(cell.x + cell.width, cell.y + cell.height),
+                LogicalPosition::new(0.0, 0.0), LogicalPosition::new(1.0, 1.0),
+            ))
+            .collect();
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        Some(Self {
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            render_pipeline,
+            dimmed_render_pipeline,
+            vertex_buffer,
+            bind_groups,
+            theme_bind_group,
+            hovered: None,
+        })
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = self.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.05,
+                            g: 0.05,
+                            b: 0.05,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_bind_group(1, &self.theme_bind_group, &[]);
+            for (index, bind_group) in self.bind_groups.iter().enumerate() {
+                if self.hovered == Some(index) {
+                    render_pass.set_pipeline(&self.render_pipeline);
+                } else {
+                    render_pass.set_pipeline(&self.dimmed_render_pipeline);
+                }
+                render_pass.set_bind_group(0, bind_group, &[]);
+                let first = index as u32 * 6;
+                render_pass.draw(first..first + 6, 0..1);
+            }
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+
+        Ok(())
+    }
+}
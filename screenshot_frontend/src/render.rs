@@ -0,0 +1,377 @@
+use image::{DynamicImage, GenericImageView};
+use raw_window_handle::HasRawWindowHandle;
+use wgpu::util::DeviceExt;
+use winit::dpi::{LogicalPosition, PhysicalSize};
+
+use crate::color;
+use crate::config::{PresentMode, RenderConfig, ThemeConfig};
+use crate::soft::{self, SoftState};
+use crate::state::State;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+impl Vertex {
+    pub fn new(position: LogicalPosition<f32>, tex_coords: LogicalPosition<f32>) -> Self {
+        Self { position: [position.x, position.y, 0.0], tex_coords: [tex_coords.x, tex_coords.y] }
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                }
+            ]
+        }
+    }
+}
+
+/// A vertex of a solid coloured shape.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ColorVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl ColorVertex {
+    pub fn new(position: LogicalPosition<f32>, color: [f32; 4]) -> Self {
+        Self { position: [position.x, position.y], color }
+    }
+
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ColorVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                }
+            ]
+        }
+    }
+}
+
+/// Draws the overlay, on the GPU with `State` or on the CPU with `SoftState`.
+pub trait Renderer {
+    fn size(&self) -> PhysicalSize<u32>;
+    fn resize(&mut self, new_size: PhysicalSize<u32>);
+    /// Highlights the selection, given as triangles in clip space.
+    fn update(&mut self, triangles: &[LogicalPosition<f32>]);
+    /// Stops highlighting a selection.
+    fn clear_selection(&mut self);
+    /// Shows the part of the image between `tex1` and `tex2` magnified between `pos1` and `pos2`
+    /// (in clip space).
+    fn update_loupe(&mut self, pos1: LogicalPosition<f32>, pos2: LogicalPosition<f32>, tex1: LogicalPosition<f32>, tex2: LogicalPosition<f32>);
+    /// Draws labels made with `text::vertices`.
+    fn update_text(&mut self, vertices: &[Vertex]);
+    /// Draws solid marks such as the selection's handles, given as triangles in clip space.
+    fn update_marks(&mut self, vert: &[ColorVertex]);
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError>;
+}
+
+/// Draws on the GPU, or on the CPU when there is no adapter or the config asks for it.
+/// Fails when the CPU can't show frames on this display either.
+pub fn renderer(window: &impl HasRawWindowHandle, size: PhysicalSize<u32>, img: &DynamicImage, theme: &ThemeConfig, config: RenderConfig) -> Result<Box<dyn Renderer>, String> {
+    if !config.software {
+        if let Some(state) = pollster::block_on(State::new(window, size, img, theme, config.present_mode)) {
+            return Ok(Box::new(state));
+        }
+        eprintln!("No graphics adapter found, drawing the overlay on the CPU.");
+    }
+    let framebuffer = soft::framebuffer(window).ok_or("Can't show the overlay without a GPU on this display")?;
+    Ok(Box::new(SoftState::new(size, img, theme, framebuffer)))
+}
+
+/// How `shader.wgsl` dims the screen, laid out like its `Theme` struct.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Theme {
+    tint: [f32; 4],
+    dim: f32,
+    // Uniforms are a multiple of 16 bytes long.
+    _padding: [f32; 3],
+}
+
+impl Theme {
+    pub fn new(config: &ThemeConfig) -> Self {
+        Self { tint: color::linear(config.tint.0), dim: config.dim.clamp(0.0, 1.0), _padding: [0.0; 3] }
+    }
+}
+
+pub const VERTICES: &[Vertex] = &[
+    Vertex { position: [-1.0, 1.0, 0.0], tex_coords: [0.0, 0.0] },
+    Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0] },
+    Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0] },
+
+    Vertex { position: [1.0, 1.0, 0.0], tex_coords: [1.0, 0.0] },
+    Vertex { position: [-1.0, -1.0, 0.0], tex_coords: [0.0, 1.0] },
+    Vertex { position: [1.0, -1.0, 0.0], tex_coords: [1.0, 1.0] },
+];
+
+/// Two triangles covering the rectangle between `pos1` and `pos2` (in clip space),
+/// showing the given part of the texture.
+pub fn quad(pos1: LogicalPosition<f32>, pos2: LogicalPosition<f32>, tex1: LogicalPosition<f32>, tex2: LogicalPosition<f32>) -> [Vertex; 6] {
+    [
+        Vertex { position: [pos1.x, pos1.y, 0.0], tex_coords: [tex1.x, tex1.y] },
+        Vertex { position: [pos1.x, pos2.y, 0.0], tex_coords: [tex1.x, tex2.y] },
+        Vertex { position: [pos2.x, pos1.y, 0.0], tex_coords: [tex2.x, tex1.y] },
+
+        Vertex { position: [pos2.x, pos1.y, 0.0], tex_coords: [tex2.x, tex1.y] },
+        Vertex { position: [pos1.x, pos2.y, 0.0], tex_coords: [tex1.x, tex2.y] },
+        Vertex { position: [pos2.x, pos2.y, 0.0], tex_coords: [tex2.x, tex2.y] },
+    ]
+}
+
+/// Nothing when no adapter can draw to `window`, as in some VMs and remote desktops.
+pub async fn init_surface(window: &impl HasRawWindowHandle, size: PhysicalSize<u32>, present_mode: PresentMode) -> Option<(wgpu::Surface, wgpu::Device, wgpu::Queue, wgpu::SurfaceConfiguration)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let surface = unsafe { instance.create_surface(window) };
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        },
+    ).await?;
+
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+            label: None,
+        },
+        None,
+    ).await.ok()?;
+
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface.get_preferred_format(&adapter).unwrap(),
+        width: size.width,
+        height: size.height,
+        present_mode: match present_mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        },
+    };
+    surface.configure(&device, &config);
+
+    Some((surface, device, queue, config))
+}
+
+pub fn texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(
+                        wgpu::SamplerBindingType::Filtering,
+                    ),
+                    count: None,
+                },
+            ],
+            label: Some("texture_bind_group_layout"),
+        }
+    )
+}
+
+pub fn uniform_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("uniform_bind_group_layout"),
+        }
+    )
+}
+
+/// Uploads `contents` as a uniform buffer for use with `uniform_bind_group_layout`.
+pub fn create_uniform_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, contents: &[u8]) -> wgpu::BindGroup {
+    let buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        }
+    );
+
+    device.create_bind_group(
+        &wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: Some("uniform_bind_group"),
+        }
+    )
+}
+
+/// Uploads `img` and binds it together with a sampler for use with `texture_bind_group_layout`.
+pub fn create_texture_bind_group(device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout, img: &DynamicImage) -> wgpu::BindGroup {
+    let rgba = img.to_rgba8();
+    let dimensions = img.dimensions();
+
+    let texture_size = wgpu::Extent3d {
+        width: dimensions.0,
+        height: dimensions.1,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(
+        &wgpu::TextureDescriptor {
+            size: texture_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            label: Some("diffuse_texture"),
+        }
+    );
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
+            rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+        },
+        texture_size,
+    );
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    device.create_bind_group(
+        &wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                }
+            ],
+            label: Some("diffuse_bind_group"),
+        }
+    )
+}
+
+/// A pipeline drawing textured `Vertex` triangles with the `vs_main` and `fs_main` entry points of `shader`.
+/// The texture is bind group 0, any further `layouts` follow it.
+pub fn create_render_pipeline(device: &wgpu::Device, layouts: &[&wgpu::BindGroupLayout], shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, cull_mode: Option<wgpu::Face>) -> wgpu::RenderPipeline {
+    pipeline(device, layouts, shader, format, cull_mode, Vertex::desc())
+}
+
+/// A pipeline drawing `ColorVertex` triangles with the `vs_main` and `fs_main` entry points of `shader`.
+pub fn create_color_pipeline(device: &wgpu::Device, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    pipeline(device, &[], shader, format, None, ColorVertex::desc())
+}
+
+fn pipeline(device: &wgpu::Device, layouts: &[&wgpu::BindGroupLayout], shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, cull_mode: Option<wgpu::Face>, buffer: wgpu::VertexBufferLayout) -> wgpu::RenderPipeline {
+    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Render Pipeline Layout"),
+        bind_group_layouts: layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(&render_pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                buffer
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }]
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
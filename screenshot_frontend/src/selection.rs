@@ -0,0 +1,300 @@
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::window::CursorIcon;
+
+use crate::shape::{self, Polygon, Shape};
+
+/// How far from a handle, in pixels, it can still be grabbed.
+pub const HANDLE_RADIUS: i32 = 6;
+
+/// The grips on the corners and edges of a selection.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    pub const ALL: [Handle; 8] = [
+        Handle::TopLeft, Handle::Top, Handle::TopRight, Handle::Right,
+        Handle::BottomRight, Handle::Bottom, Handle::BottomLeft, Handle::Left,
+    ];
+
+    // Which edges the handle moves: -1 for left/top, 1 for right/bottom and 0 for neither.
+    fn edges(self) -> (i32, i32) {
+        match self {
+            Handle::TopLeft => (-1, -1),
+            Handle::Top => (0, -1),
+            Handle::TopRight => (1, -1),
+            Handle::Right => (1, 0),
+            Handle::BottomRight => (1, 1),
+            Handle::Bottom => (0, 1),
+            Handle::BottomLeft => (-1, 1),
+            Handle::Left => (-1, 0),
+        }
+    }
+
+    pub fn cursor(self) -> CursorIcon {
+        match self {
+            Handle::TopLeft => CursorIcon::NwResize,
+            Handle::Top => CursorIcon::NResize,
+            Handle::TopRight => CursorIcon::NeResize,
+            Handle::Right => CursorIcon::EResize,
+            Handle::BottomRight => CursorIcon::SeResize,
+            Handle::Bottom => CursorIcon::SResize,
+            Handle::BottomLeft => CursorIcon::SwResize,
+            Handle::Left => CursorIcon::WResize,
+        }
+    }
+}
+
+/// A selected area that can still be moved and resized, in image pixels.
+#[derive(Clone)]
+pub struct Selection {
+    pub shape: Shape,
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    // Lasso points as fractions of the bounding box, so they follow moves and resizes.
+    path: Vec<(f32, f32)>,
+}
+
+impl Selection {
+    /// A rectangle or ellipse spanning the rectangle between two corners.
+    pub fn new(shape: Shape, corner1: PhysicalPosition<u32>, corner2: PhysicalPosition<u32>) -> Self {
+        Self {
+            shape,
+            left: corner1.x.min(corner2.x) as i32,
+            top: corner1.y.min(corner2.y) as i32,
+            right: corner1.x.max(corner2.x) as i32,
+            bottom: corner1.y.max(corner2.y) as i32,
+            path: vec![],
+        }
+    }
+
+    /// A lasso following `path`.
+    pub fn lasso(path: &[PhysicalPosition<u32>]) -> Self {
+        let left = path.iter().map(|point| point.x).min().unwrap_or(0) as i32;
+        let top = path.iter().map(|point| point.y).min().unwrap_or(0) as i32;
+        let right = path.iter().map(|point| point.x).max().unwrap_or(0) as i32;
+        let bottom = path.iter().map(|point| point.y).max().unwrap_or(0) as i32;
+        let fraction = |value: u32, start: i32, end: i32| if end > start { (value as i32 - start) as f32 / (end - start) as f32 } else { 0.0 };
+        Self {
+            shape: Shape::Lasso,
+            left,
+            top,
+            right,
+            bottom,
+            path: path.iter().map(|point| (fraction(point.x, left, right), fraction(point.y, top, bottom))).collect(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        (self.right - self.left) as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        (self.bottom - self.top) as u32
+    }
+
+    /// Whether the bounding box contains `position`.
+    pub fn contains(&self, position: PhysicalPosition<u32>) -> bool {
+        let (x, y) = (position.x as i32, position.y as i32);
+        x >= self.left && x < self.right && y >= self.top && y < self.bottom
+    }
+
+    /// Where the handle sits.
+    pub fn handle_position(&self, handle: Handle) -> (i32, i32) {
+        let (horizontal, vertical) = handle.edges();
+        let x = match horizontal {
+            -1 => self.left,
+            1 => self.right,
+            _ => (self.left + self.right) / 2,
+        };
+        let y = match vertical {
+            -1 => self.top,
+            1 => self.bottom,
+            _ => (self.top + self.bottom) / 2,
+        };
+        (x, y)
+    }
+
+    /// The handle that can be grabbed at `position`, if any.
+    pub fn handle_at(&self, position: PhysicalPosition<u32>) -> Option<Handle> {
+        Handle::ALL.into_iter().find(|handle| {
+            let (x, y) = self.handle_position(*handle);
+            (position.x as i32 - x).abs() <= HANDLE_RADIUS && (position.y as i32 - y).abs() <= HANDLE_RADIUS
+        })
+    }
+
+    /// Moved by the given offset, but kept inside a `width` by `height` image. A selection
+    /// larger than the image keeps its left and top edges inside.
+    pub fn moved(&self, dx: i32, dy: i32, width: u32, height: u32) -> Self {
+        // Not `clamp`, which panics when the selection doesn't fit.
+        let dx = dx.min(width as i32 - self.right).max(-self.left);
+        let dy = dy.min(height as i32 - self.bottom).max(-self.top);
+        Self {
+            left: self.left + dx,
+            top: self.top + dy,
+            right: self.right + dx,
+            bottom: self.bottom + dy,
+            ..self.clone()
+        }
+    }
+
+    /// With the edges of `handle` moved to `position`. Dragging a handle past the
+    /// opposite edge flips the selection.
+    pub fn resized(&self, handle: Handle, position: PhysicalPosition<u32>) -> Self {
+        let (horizontal, vertical) = handle.edges();
+        let mut resized = self.clone();
+        match horizontal {
+            -1 => resized.left = position.x as i32,
+            1 => resized.right = position.x as i32,
+            _ => {}
+        }
+        match vertical {
+            -1 => resized.top = position.y as i32,
+            1 => resized.bottom = position.y as i32,
+            _ => {}
+        }
+        if resized.left > resized.right {
+            std::mem::swap(&mut resized.left, &mut resized.right);
+            resized.path.iter_mut().for_each(|point| point.0 = 1.0 - point.0);
+        }
+        if resized.top > resized.bottom {
+            std::mem::swap(&mut resized.top, &mut resized.bottom);
+            resized.path.iter_mut().for_each(|point| point.1 = 1.0 - point.1);
+        }
+        resized
+    }
+
+    /// Like `resized`, but keeping the width to height `ratio`. Corner handles keep the
+    /// opposite corner in place, edge handles the opposite edge and the left or top one.
+    /// Near the bounds both sides shrink together.
+    pub fn resized_locked(&self, handle: Handle, position: PhysicalPosition<u32>, ratio: (u32, u32), bounds: PhysicalSize<u32>) -> Self {
+        // An edge handle works like the corner that grows right or down, with only the
+        // dragged side following the cursor.
+        let (corner, end) = match handle.edges() {
+            (0, vertical) => (
+                if vertical == 1 { Handle::BottomRight } else { Handle::TopRight },
+                PhysicalPosition::new(self.left as u32, position.y),
+            ),
+            (horizontal, 0) => (
+                if horizontal == 1 { Handle::BottomRight } else { Handle::BottomLeft },
+                PhysicalPosition::new(position.x, self.top as u32),
+            ),
+            _ => (handle, position),
+        };
+        let (horizontal, vertical) = corner.edges();
+        let anchor = PhysicalPosition::new(
+            if horizontal == 1 { self.left } else { self.right } as u32,
+            if vertical == 1 { self.top } else { self.bottom } as u32,
+        );
+        self.resized(corner, lock_ratio(anchor, end, ratio, bounds))
+    }
+
+    /// The selected area itself.
+    pub fn outline(&self) -> Polygon {
+        let corner1 = PhysicalPosition::new(self.left.max(0) as u32, self.top.max(0) as u32);
+        let corner2 = PhysicalPosition::new(self.right.max(0) as u32, self.bottom.max(0) as u32);
+        match self.shape {
+            Shape::Rectangle => shape::rectangle(corner1, corner2),
+            Shape::Ellipse => shape::ellipse(corner1, corner2),
+            Shape::Lasso => self.path.iter()
+                .map(|(x, y)| (self.left as f32 + x * self.width() as f32, self.top as f32 + y * self.height() as f32))
+                .collect(),
+        }
+    }
+}
+
+/// Where the corner opposite `start` goes for a box with the width to height `ratio`
+/// that reaches towards `end`, but stays inside `bounds`.
+pub fn lock_ratio(start: PhysicalPosition<u32>, end: PhysicalPosition<u32>, ratio: (u32, u32), bounds: PhysicalSize<u32>) -> PhysicalPosition<u32> {
+    let (ratio_width, ratio_height) = (ratio.0 as u64, ratio.1 as u64);
+    let (mut width, mut height) = (start.x.abs_diff(end.x) as u64, start.y.abs_diff(end.y) as u64);
+    // The box grows to cover the cursor in both directions.
+    if width * ratio_height >= height * ratio_width {
+        height = width * ratio_height / ratio_width;
+    } else {
+        width = height * ratio_width / ratio_height;
+    }
+    let room_x = if end.x >= start.x { bounds.width.saturating_sub(start.x) } else { start.x } as u64;
+    let room_y = if end.y >= start.y { bounds.height.saturating_sub(start.y) } else { start.y } as u64;
+    if width > room_x {
+        width = room_x;
+        height = width * ratio_height / ratio_width;
+    }
+    if height > room_y {
+        height = room_y;
+        width = height * ratio_width / ratio_height;
+    }
+    let (width, height) = (width as u32, height as u32);
+    PhysicalPosition::new(
+        if end.x >= start.x { start.x + width } else { start.x - width },
+        if end.y >= start.y { start.y + height } else { start.y - height },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(start: (u32, u32), end: (u32, u32), ratio: (u32, u32)) -> (u32, u32) {
+        let corner = lock_ratio(PhysicalPosition::new(start.0, start.1), PhysicalPosition::new(end.0, end.1), ratio, PhysicalSize::new(1920, 1080));
+        (corner.x, corner.y)
+    }
+
+    #[test]
+    fn lock_ratio_grows_to_cover_the_cursor() {
+        assert_eq!(lock((100, 100), (260, 120), (16, 9)), (260, 190));
+        assert_eq!(lock((100, 100), (120, 190), (16, 9)), (260, 190));
+        assert_eq!(lock((500, 500), (300, 480), (1, 1)), (300, 300));
+    }
+
+    #[test]
+    fn lock_ratio_stays_inside_the_bounds() {
+        assert_eq!(lock((1800, 100), (1900, 300), (1, 1)), (1920, 220));
+        assert_eq!(lock((100, 1000), (200, 1080), (4, 3)), (206, 1080));
+        assert_eq!(lock((50, 500), (0, 400), (2, 1)), (0, 475));
+    }
+
+    #[test]
+    fn resized_locked_keeps_the_ratio_at_the_bounds() {
+        let selection = Selection::new(Shape::Rectangle, PhysicalPosition::new(1800, 100), PhysicalPosition::new(1816, 109));
+        let bounds = PhysicalSize::new(1920, 1080);
+        for (handle, position) in [(Handle::Right, (1950, 0)), (Handle::Bottom, (0, 300)), (Handle::BottomRight, (1950, 300))] {
+            let resized = selection.resized_locked(handle, PhysicalPosition::new(position.0, position.1), (16, 9), bounds);
+            assert_eq!((resized.left, resized.top, resized.right, resized.bottom), (1800, 100, 1920, 167));
+        }
+    }
+
+    #[test]
+    fn resized_locked_follows_edge_handles() {
+        let selection = Selection::new(Shape::Rectangle, PhysicalPosition::new(100, 100), PhysicalPosition::new(260, 190));
+        let bounds = PhysicalSize::new(1920, 1080);
+        let resized = selection.resized_locked(Handle::Top, PhysicalPosition::new(0, 10), (16, 9), bounds);
+        assert_eq!((resized.left, resized.top, resized.right, resized.bottom), (100, 10, 420, 190));
+        let resized = selection.resized_locked(Handle::Left, PhysicalPosition::new(20, 0), (16, 9), bounds);
+        assert_eq!((resized.left, resized.top, resized.right, resized.bottom), (20, 100, 260, 235));
+    }
+
+    #[test]
+    fn moved_stays_inside_the_image() {
+        let selection = Selection::new(Shape::Rectangle, PhysicalPosition::new(10, 20), PhysicalPosition::new(110, 70));
+        let moved = selection.moved(-50, 1000, 200, 100);
+        assert_eq!((moved.left, moved.top, moved.right, moved.bottom), (0, 50, 100, 100));
+    }
+
+    #[test]
+    fn moved_keeps_a_selection_larger_than_the_image() {
+        let selection = Selection::new(Shape::Rectangle, PhysicalPosition::new(10, 10), PhysicalPosition::new(310, 210));
+        let moved = selection.moved(40, -40, 200, 100);
+        assert_eq!((moved.left, moved.top, moved.right, moved.bottom), (0, 0, 300, 200));
+    }
+}
@@ -24,7 +24,18 @@ var t_diffuse: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
 
+struct Theme {
+    tint: vec4<f32>;
+    dim: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> theme: Theme;
+
+// The selection is drawn over this at full brightness, so only the rest of the
+// screen ends up dimmed.
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords) * 0.4;
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(mix(color.rgb, theme.tint.rgb, theme.dim), 1.0);
 }
\ No newline at end of file
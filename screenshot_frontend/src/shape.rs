@@ -0,0 +1,106 @@
+use image::RgbaImage;
+use winit::dpi::PhysicalPosition;
+
+// Corners of the polygon standing in for an ellipse.
+const ELLIPSE_SEGMENTS: usize = 128;
+
+/// The kind of area a drag selects.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Rectangle,
+    Ellipse,
+    /// Follows the cursor, the path is closed with a straight line.
+    Lasso,
+}
+
+/// A closed polygon in image pixels.
+pub type Polygon = Vec<(f32, f32)>;
+
+pub fn rectangle(corner1: PhysicalPosition<u32>, corner2: PhysicalPosition<u32>) -> Polygon {
+    rectangle_f32(corner1.x as f32, corner1.y as f32, corner2.x as f32, corner2.y as f32)
+}
+
+pub fn rectangle_f32(x1: f32, y1: f32, x2: f32, y2: f32) -> Polygon {
+    vec![(x1, y1), (x2, y1), (x2, y2), (x1, y2)]
+}
+
+/// The ellipse inscribed in the rectangle between the corners.
+pub fn ellipse(corner1: PhysicalPosition<u32>, corner2: PhysicalPosition<u32>) -> Polygon {
+    ellipse_f32(corner1.x as f32, corner1.y as f32, corner2.x as f32, corner2.y as f32)
+}
+
+pub fn ellipse_f32(x1: f32, y1: f32, x2: f32, y2: f32) -> Polygon {
+    let center = ((x1 + x2) / 2.0, (y1 + y2) / 2.0);
+    let radius = ((x1 - x2).abs() / 2.0, (y1 - y2).abs() / 2.0);
+    (0..ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+            (center.0 + radius.0 * angle.cos(), center.1 + radius.1 * angle.sin())
+        })
+        .collect()
+}
+
+/// Where the polygon covers the row at height `y`, as pairs of x coordinates.
+///
+/// Uses the even-odd rule, so a lasso crossing itself leaves the overlap out.
+pub fn spans(polygon: &[(f32, f32)], y: f32) -> Vec<(f32, f32)> {
+    let mut crossings: Vec<f32> = edges(polygon)
+        .filter(|(a, b)| (a.1 <= y) != (b.1 <= y))
+        .map(|(a, b)| x_at(a, b, y))
+        .collect();
+    crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    crossings.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+/// Splits the polygon into triangles for drawing, three points each.
+///
+/// The polygon is cut into horizontal bands at its corners. Within a band the
+/// covered parts are trapezoids between pairs of edges.
+pub fn triangles(polygon: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut heights: Vec<f32> = polygon.iter().map(|point| point.1).collect();
+    heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    heights.dedup();
+
+    let mut triangles = vec![];
+    for band in heights.windows(2) {
+        let (top, bottom) = (band[0], band[1]);
+        let middle = (top + bottom) / 2.0;
+        // Edges crossing the band as their x at the middle, top and bottom of it.
+        let mut crossings: Vec<(f32, f32, f32)> = edges(polygon)
+            .filter(|(a, b)| (a.1 <= middle) != (b.1 <= middle))
+            .map(|(a, b)| (x_at(a, b, middle), x_at(a, b, top), x_at(a, b, bottom)))
+            .collect();
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        for pair in crossings.chunks_exact(2) {
+            let (left, right) = (pair[0], pair[1]);
+            triangles.extend_from_slice(&[
+                (left.1, top), (left.2, bottom), (right.1, top),
+                (right.1, top), (left.2, bottom), (right.2, bottom),
+            ]);
+        }
+    }
+    triangles
+}
+
+/// Makes the pixels of `image` outside the polygon transparent. `origin` is where
+/// the image's top left corner is in the polygon's coordinates.
+pub fn mask(image: &mut RgbaImage, polygon: &[(f32, f32)], origin: PhysicalPosition<u32>) {
+    for y in 0..image.height() {
+        // Pixels are sampled at their centre.
+        let spans = spans(polygon, (origin.y + y) as f32 + 0.5);
+        for x in 0..image.width() {
+            let center = (origin.x + x) as f32 + 0.5;
+            if !spans.iter().any(|&(left, right)| center >= left && center < right) {
+                image.get_pixel_mut(x, y).0 = [0, 0, 0, 0];
+            }
+        }
+    }
+}
+
+fn edges(polygon: &[(f32, f32)]) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + '_ {
+    polygon.iter().copied().zip(polygon.iter().copied().cycle().skip(1))
+}
+
+fn x_at(a: (f32, f32), b: (f32, f32), y: f32) -> f32 {
+    a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1)
+}
@@ -0,0 +1,409 @@
+use std::error::Error;
+use std::fs::File;
+use std::os::fd::AsFd;
+use std::os::unix::fs::FileExt;
+
+use image::{DynamicImage, RgbaImage};
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+use rustix::fs::{memfd_create, MemfdFlags};
+use wayland_client::backend::{Backend, ObjectId};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_buffer::{self, WlBuffer}, wl_registry::WlRegistry, wl_shm::{self, WlShm},
+    wl_shm_pool::WlShmPool, wl_surface::WlSurface};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle};
+use winit::dpi::{LogicalPosition, PhysicalSize};
+use x11rb::connection::{Connection as _, RequestConnection};
+use x11rb::protocol::xproto::{ConnectionExt, CreateGCAux, ImageFormat};
+use x11rb::rust_connection::RustConnection;
+
+use crate::color;
+use crate::config::ThemeConfig;
+use crate::render::{ColorVertex, Renderer, Vertex};
+use crate::text;
+
+// How much darker the lines between the loupe's pixels are, like in `loupe.wgsl`.
+const GRID_SHADE: f32 = 0.6;
+
+/// Where `SoftState` shows its frames.
+pub trait Framebuffer {
+    /// Shows `pixels`, rows of `size.width` values packed like `pack`.
+    fn present(&mut self, pixels: &[u32], size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>>;
+}
+
+/// Draws the overlay into memory the way the shaders would, for machines without
+/// a usable GPU.
+pub struct SoftState {
+    size: PhysicalSize<u32>,
+    image: RgbaImage,
+    atlas: RgbaImage,
+    // How each channel value of the image looks outside the selection,
+    dimmed: [[u8; 256]; 3],
+    // and between the loupe's pixels.
+    shaded: [u8; 256],
+    /// The dimmed image at the size of the window, the start of every frame.
+    background: Vec<u32>,
+    /// The selection's triangles in window pixels.
+    selection: Vec<(f32, f32)>,
+    loupe: Vec<Vertex>,
+    text: Vec<Vertex>,
+    marks: Vec<ColorVertex>,
+    framebuffer: Box<dyn Framebuffer>,
+    /// Whether the last frame couldn't be shown.
+    failed: bool,
+}
+
+impl SoftState {
+    pub fn new(size: PhysicalSize<u32>, img: &DynamicImage, theme: &ThemeConfig, framebuffer: Box<dyn Framebuffer>) -> Self {
+        // The GPU mixes in linear light, so this does too.
+        let linear = |value: u8| color::linear([value; 3])[0];
+        let tint = color::linear(theme.tint.0);
+        let dim = theme.dim.clamp(0.0, 1.0);
+        let dimmed = [0, 1, 2].map(|channel| {
+            std::array::from_fn(|value| srgb(linear(value as u8) * (1.0 - dim) + tint[channel] * dim))
+        });
+        let mut state = Self {
+            size,
+            image: img.to_rgba8(),
+            atlas: text::atlas().to_rgba8(),
+            dimmed,
+            shaded: std::array::from_fn(|value| srgb(linear(value as u8) * GRID_SHADE)),
+            background: vec![],
+            selection: vec![],
+            loupe: vec![],
+            text: vec![],
+            marks: vec![],
+            framebuffer,
+            failed: false,
+        };
+        state.resize(size);
+        state
+    }
+
+    // From clip space to window pixels.
+    fn pixel(&self, x: f32, y: f32) -> (f32, f32) {
+        ((x + 1.0) / 2.0 * self.size.width as f32, (1.0 - y) / 2.0 * self.size.height as f32)
+    }
+
+    // The image pixel shown at window pixel `x`, `y`, the image is stretched over the window.
+    fn image_pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let (width, height) = self.image.dimensions();
+        let x = (x as u64 * width as u64 / self.size.width as u64) as u32;
+        let y = (y as u64 * height as u64 / self.size.height as u64) as u32;
+        let [r, g, b, _] = self.image.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+        [r, g, b]
+    }
+
+    // Calls `paint` with the window pixel and the texture coordinates for every pixel of the
+    // quads made by `render::quad`.
+    fn textured(&self, vertices: &[Vertex], mut paint: impl FnMut(usize, (f32, f32), (f32, f32))) {
+        for quad in vertices.chunks_exact(6) {
+            // The first and last corners are opposite each other.
+            let (first, last) = (quad[0], quad[5]);
+            let from = self.pixel(first.position[0], first.position[1]);
+            let to = self.pixel(last.position[0], last.position[1]);
+            let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+            let (top, bottom) = (from.1.min(to.1), from.1.max(to.1));
+            // Texture coordinates change this much from one pixel to the next.
+            let step = (
+                (last.tex_coords[0] - first.tex_coords[0]) / (to.0 - from.0),
+                (last.tex_coords[1] - first.tex_coords[1]) / (to.1 - from.1),
+            );
+            for y in span(top, bottom, self.size.height) {
+                for x in span(left, right, self.size.width) {
+                    let tex = (
+                        first.tex_coords[0] + (x as f32 + 0.5 - from.0) * step.0,
+                        first.tex_coords[1] + (y as f32 + 0.5 - from.1) * step.1,
+                    );
+                    paint(y * self.size.width as usize + x, tex, (step.0.abs(), step.1.abs()));
+                }
+            }
+        }
+    }
+
+    fn draw_loupe(&self, frame: &mut [u32]) {
+        let (width, height) = self.image.dimensions();
+        self.textured(&self.loupe, |index, tex, step| {
+            let texel = (tex.0 * width as f32, tex.1 * height as f32);
+            let grid = (step.0 * width as f32, step.1 * height as f32);
+            frame[index] = if texel.0 < 0.0 || texel.1 < 0.0 || texel.0 >= width as f32 || texel.1 >= height as f32 {
+                pack([0, 0, 0])
+            } else {
+                let [r, g, b, _] = self.image.get_pixel(texel.0 as u32, texel.1 as u32).0;
+                if texel.0.fract() < grid.0 || texel.1.fract() < grid.1 {
+                    pack([self.shaded[r as usize], self.shaded[g as usize], self.shaded[b as usize]])
+                } else {
+                    pack([r, g, b])
+                }
+            };
+        });
+    }
+
+    fn draw_text(&self, frame: &mut [u32]) {
+        let (width, height) = self.atlas.dimensions();
+        self.textured(&self.text, |index, tex, _| {
+            let x = (tex.0 * width as f32).clamp(0.0, width as f32 - 1.0);
+            let y = (tex.1 * height as f32).clamp(0.0, height as f32 - 1.0);
+            let [r, g, b, _] = self.atlas.get_pixel(x as u32, y as u32).0;
+            frame[index] = pack([r, g, b]);
+        });
+    }
+}
+
+impl Renderer for SoftState {
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            let (width, height) = (new_size.width as usize, new_size.height as usize);
+            self.background = (0..width * height).map(|index| {
+                let [r, g, b] = self.image_pixel(index % width, index / width);
+                pack([self.dimmed[0][r as usize], self.dimmed[1][g as usize], self.dimmed[2][b as usize]])
+            }).collect();
+        }
+    }
+
+    fn update(&mut self, triangles: &[LogicalPosition<f32>]) {
+        self.selection = triangles.iter().map(|position| self.pixel(position.x, position.y)).collect();
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection.clear();
+    }
+
+    fn update_loupe(&mut self, pos1: LogicalPosition<f32>, pos2: LogicalPosition<f32>, tex1: LogicalPosition<f32>, tex2: LogicalPosition<f32>) {
+        self.loupe = crate::render::quad(pos1, pos2, tex1, tex2).to_vec();
+    }
+
+    fn update_text(&mut self, vertices: &[Vertex]) {
+        self.text = vertices.to_vec();
+    }
+
+    fn update_marks(&mut self, vert: &[ColorVertex]) {
+        self.marks = vert.to_vec();
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let mut frame = self.background.clone();
+        let width = self.size.width as usize;
+        for triangle in self.selection.chunks_exact(3) {
+            fill(triangle, self.size, |index| {
+                frame[index] = pack(self.image_pixel(index % width, index / width));
+            });
+        }
+        self.draw_loupe(&mut frame);
+        for triangle in self.marks.chunks_exact(3) {
+            let [r, g, b, _] = triangle[0].color;
+            let color = pack([srgb(r), srgb(g), srgb(b)]);
+            let points: Vec<(f32, f32)> = triangle.iter().map(|vertex| self.pixel(vertex.position[0], vertex.position[1])).collect();
+            fill(&points, self.size, |index| frame[index] = color);
+        }
+        self.draw_text(&mut frame);
+        match self.framebuffer.present(&frame, self.size) {
+            Ok(()) => {
+                self.failed = false;
+                Ok(())
+            },
+            Err(e) => {
+                eprintln!("Could not show the overlay: {}", e);
+                // Tried once more after a resize like a lost surface, then given up on the
+                // way wgpu gives up on a device.
+                let again = std::mem::replace(&mut self.failed, true);
+                Err(if again { wgpu::SurfaceError::OutOfMemory } else { wgpu::SurfaceError::Lost })
+            },
+        }
+    }
+}
+
+// Rows or columns whose centres lie between `start` and `end`, within `0..length`.
+fn span(start: f32, end: f32, length: u32) -> std::ops::Range<usize> {
+    let first = (start - 0.5).ceil().max(0.0) as usize;
+    let last = ((end - 0.5).ceil().max(0.0) as usize).min(length as usize);
+    first..last.max(first)
+}
+
+// Calls `paint` with the index of every pixel whose centre is inside the triangle.
+fn fill(triangle: &[(f32, f32)], size: PhysicalSize<u32>, mut paint: impl FnMut(usize)) {
+    let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+    let edge = |from: (f32, f32), to: (f32, f32), point: (f32, f32)| (to.0 - from.0) * (point.1 - from.1) - (to.1 - from.1) * (point.0 - from.0);
+    let area = edge(a, b, c);
+    if area == 0.0 {
+        return;
+    }
+    let left = a.0.min(b.0).min(c.0);
+    let right = a.0.max(b.0).max(c.0);
+    let top = a.1.min(b.1).min(c.1);
+    let bottom = a.1.max(b.1).max(c.1);
+    for y in span(top, bottom, size.height) {
+        for x in span(left, right, size.width) {
+            let point = (x as f32 + 0.5, y as f32 + 0.5);
+            // On the same side of all three edges, whichever way the triangle winds.
+            let sides = [edge(a, b, point), edge(b, c, point), edge(c, a, point)];
+            if sides.iter().all(|side| side * area >= 0.0) {
+                paint(y * size.width as usize + x);
+            }
+        }
+    }
+}
+
+// Back from linear light to the sRGB the screen expects.
+fn srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let value = if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+    (value * 255.0).round() as u8
+}
+
+// A pixel as X11 and XRGB8888 shared memory both take it, blue in the lowest byte. The
+// unused top byte is opaque for windows that do have alpha.
+fn pack([r, g, b]: [u8; 3]) -> u32 {
+    0xff00_0000 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+/// Where to show the frames for `window`, if its display is supported.
+pub fn framebuffer(window: &impl HasRawWindowHandle) -> Option<Box<dyn Framebuffer>> {
+    match window.raw_window_handle() {
+        RawWindowHandle::Xlib(handle) => Some(Box::new(XFramebuffer::new(handle.window as u32)?)),
+        RawWindowHandle::Xcb(handle) => Some(Box::new(XFramebuffer::new(handle.window)?)),
+        RawWindowHandle::Wayland(handle) => {
+            // The display and surface belong to winit or to the layer shell, this only borrows them.
+            let conn = Connection::from_backend(unsafe { Backend::from_foreign_display(handle.display as *mut _) });
+            let id = unsafe { ObjectId::from_ptr(WlSurface::interface(), handle.surface as *mut _) }.ok()?;
+            let surface = WlSurface::from_id(&conn, id).ok()?;
+            Some(Box::new(ShmFramebuffer::new(conn, surface)?))
+        },
+        _ => None,
+    }
+}
+
+/// Sends frames to an X11 window as images.
+struct XFramebuffer {
+    conn: RustConnection,
+    window: u32,
+    gc: u32,
+    depth: u8,
+}
+
+impl XFramebuffer {
+    fn new(window: u32) -> Option<Self> {
+        let (conn, _) = x11rb::connect(None).ok()?;
+        let depth = conn.get_geometry(window).ok()?.reply().ok()?.depth;
+        let gc = conn.generate_id().ok()?;
+        conn.create_gc(gc, window, &CreateGCAux::new()).ok()?;
+        Some(Self { conn, window, gc, depth })
+    }
+}
+
+impl Framebuffer for XFramebuffer {
+    fn present(&mut self, pixels: &[u32], size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>> {
+        let row = size.width as usize;
+        // Whole screens are larger than a single request may be.
+        let rows = ((self.conn.maximum_request_bytes() - 32) / (row * 4)).max(1);
+        for (i, chunk) in pixels.chunks(row * rows).enumerate() {
+            let height = (chunk.len() / row) as u16;
+            let y = (i * rows) as i16;
+            self.conn.put_image(ImageFormat::Z_PIXMAP, self.window, self.gc, size.width as u16, height, 0, y, 0, self.depth, bytemuck::cast_slice(chunk))?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// Attaches frames to a Wayland surface through shared memory.
+struct ShmFramebuffer {
+    conn: Connection,
+    queue: EventQueue<Buffers>,
+    buffers: Buffers,
+    shm: WlShm,
+    surface: WlSurface,
+    file: File,
+    pool: Option<(WlShmPool, PhysicalSize<u32>)>,
+}
+
+/// The buffers in the pool, one frame each, and whether the compositor still reads them.
+#[derive(Default)]
+struct Buffers {
+    buffers: Vec<WlBuffer>,
+    busy: Vec<bool>,
+}
+
+impl ShmFramebuffer {
+    fn new(conn: Connection, surface: WlSurface) -> Option<Self> {
+        let (globals, queue) = registry_queue_init::<Buffers>(&conn).ok()?;
+        let shm: WlShm = globals.bind(&queue.handle(), 1..=1, ()).ok()?;
+        let fd = memfd_create("sss-overlay", MemfdFlags::CLOEXEC).ok()?;
+        Some(Self { conn, queue, buffers: Buffers::default(), shm, surface, file: File::from(fd), pool: None })
+    }
+}
+
+impl Framebuffer for ShmFramebuffer {
+    fn present(&mut self, pixels: &[u32], size: PhysicalSize<u32>) -> Result<(), Box<dyn Error>> {
+        // Picks up which buffers were released since the last frame.
+        self.queue.dispatch_pending(&mut self.buffers)?;
+        let frame = size.width as usize * size.height as usize * 4;
+        if self.pool.as_ref().is_none_or(|(_, pool_size)| *pool_size != size) {
+            for buffer in self.buffers.buffers.drain(..) {
+                buffer.destroy();
+            }
+            self.buffers.busy.clear();
+            if let Some((pool, _)) = self.pool.take() {
+                pool.destroy();
+            }
+            self.file.set_len(frame as u64)?;
+            self.pool = Some((self.shm.create_pool(self.file.as_fd(), frame as i32, &self.queue.handle(), ()), size));
+        }
+        let index = match self.buffers.busy.iter().position(|busy| !busy) {
+            Some(index) => index,
+            None => {
+                // Every buffer is still on screen, so the pool grows by one.
+                let index = self.buffers.buffers.len();
+                let (pool, _) = self.pool.as_ref().unwrap();
+                self.file.set_len(((index + 1) * frame) as u64)?;
+                pool.resize(((index + 1) * frame) as i32);
+                let buffer = pool.create_buffer(
+                    (index * frame) as i32, size.width as i32, size.height as i32, size.width as i32 * 4,
+                    wl_shm::Format::Xrgb8888, &self.queue.handle(), index,
+                );
+                self.buffers.buffers.push(buffer);
+                self.buffers.busy.push(false);
+                index
+            },
+        };
+        self.file.write_all_at(bytemuck::cast_slice(pixels), (index * frame) as u64)?;
+        self.surface.attach(Some(&self.buffers.buffers[index]), 0, 0);
+        // damage_buffer came with version 4 of wl_compositor and the surface may be older. The
+        // buffer size covers the whole surface at any buffer scale, so it works for both.
+        if self.surface.version() >= 4 {
+            self.surface.damage_buffer(0, 0, size.width as i32, size.height as i32);
+        } else {
+            self.surface.damage(0, 0, size.width as i32, size.height as i32);
+        }
+        self.surface.commit();
+        self.buffers.busy[index] = true;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl Dispatch<WlBuffer, usize> for Buffers {
+    fn event(buffers: &mut Self, _: &WlBuffer, event: wl_buffer::Event, index: &usize, _: &Connection, _: &QueueHandle<Self>) {
+        if let wl_buffer::Event::Release = event {
+            if let Some(busy) = buffers.busy.get_mut(*index) {
+                *busy = false;
+            }
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Buffers {
+    fn event(_: &mut Self, _: &WlRegistry, _: <WlRegistry as Proxy>::Event, _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlShm, ()> for Buffers {
+    fn event(_: &mut Self, _: &WlShm, _: wl_shm::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
+
+impl Dispatch<WlShmPool, ()> for Buffers {
+    fn event(_: &mut Self, _: &WlShmPool, _: <WlShmPool as Proxy>::Event, _: &(), _: &Connection, _: &QueueHandle<Self>) {}
+}
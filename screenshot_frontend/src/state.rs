@@ -0,0 +1,249 @@
+use image::DynamicImage;
+use raw_window_handle::HasRawWindowHandle;
+use wgpu::include_wgsl;
+use wgpu::util::DeviceExt;
+use winit::dpi::{LogicalPosition, PhysicalSize};
+
+use crate::config::{PresentMode, ThemeConfig};
+use crate::render::{self, ColorVertex, Renderer, Theme, Vertex, VERTICES};
+use crate::text;
+
+// This is very messy 
+pub struct State {
+    surface: wgpu::Surface,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+    texture_bind_group: wgpu::BindGroup,
+    theme_bind_group: wgpu::BindGroup,
+
+    overlay_render_pipeline: wgpu::RenderPipeline,
+    overlay_vertices: VertexBuffer,
+
+    loupe_render_pipeline: wgpu::RenderPipeline,
+    loupe_vertices: VertexBuffer,
+
+    text_render_pipeline: wgpu::RenderPipeline,
+    text_bind_group: wgpu::BindGroup,
+    text_vertices: VertexBuffer,
+
+    mark_render_pipeline: wgpu::RenderPipeline,
+    mark_vertices: VertexBuffer,
+}
+
+impl State {
+    
+    /// Nothing when there is no GPU adapter to draw with.
+    pub async fn new(window: &impl HasRawWindowHandle, size: PhysicalSize<u32>, img: &DynamicImage, theme: &ThemeConfig, present_mode: PresentMode) -> Option<Self> {
+        let (surface, device, queue, config) = render::init_surface(window, size, present_mode).await?;
+
+        let texture_bind_group_layout = render::texture_bind_group_layout(&device);
+        let texture_bind_group = render::create_texture_bind_group(&device, &queue, &texture_bind_group_layout, img);
+
+        let uniform_bind_group_layout = render::uniform_bind_group_layout(&device);
+        let uniform = Theme::new(theme);
+        let theme_bind_group = render::create_uniform_bind_group(&device, &uniform_bind_group_layout, bytemuck::bytes_of(&uniform));
+
+        let shader = device.create_shader_module(&include_wgsl!("shader.wgsl"));
+        let render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout, &uniform_bind_group_layout], &shader, config.format, Some(wgpu::Face::Back));
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(VERTICES),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let num_vertices = VERTICES.len() as u32;
+
+        // The pipelines and buffers below are made once, redraws only rewrite the vertices.
+        // Each buffer has room for a rectangle to start with.
+        let quad_size = std::mem::size_of_val(VERTICES) as wgpu::BufferAddress;
+        let overlay_shader = device.create_shader_module(&include_wgsl!("overlay.wgsl"));
+        let overlay_render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout], &overlay_shader, config.format, None);
+        let overlay_vertices = VertexBuffer::new(&device, "Overlay Vertex Buffer", quad_size);
+
+        let loupe_shader = device.create_shader_module(&include_wgsl!("loupe.wgsl"));
+        let loupe_render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout], &loupe_shader, config.format, None);
+        let loupe_vertices = VertexBuffer::new(&device, "Loupe Vertex Buffer", quad_size);
+
+        let text_shader = device.create_shader_module(&include_wgsl!("text.wgsl"));
+        let text_render_pipeline = render::create_render_pipeline(&device, &[&texture_bind_group_layout], &text_shader, config.format, None);
+        let text_bind_group = render::create_texture_bind_group(&device, &queue, &texture_bind_group_layout, &text::atlas());
+        let text_vertices = VertexBuffer::new(&device, "Text Vertex Buffer", quad_size);
+
+        let mark_shader = device.create_shader_module(&include_wgsl!("mark.wgsl"));
+        let mark_render_pipeline = render::create_color_pipeline(&device, &mark_shader, config.format);
+        let mark_vertices = VertexBuffer::new(&device, "Mark Vertex Buffer", quad_size);
+        
+         
+        Some(Self {
+            surface,
+            device,
+            queue,
+            config,
+            size,
+            render_pipeline,
+            vertex_buffer,
+            num_vertices,
+            texture_bind_group,
+            theme_bind_group,
+            overlay_render_pipeline,
+            overlay_vertices,
+            loupe_render_pipeline,
+            loupe_vertices,
+            text_render_pipeline,
+            text_bind_group,
+            text_vertices,
+            mark_render_pipeline,
+            mark_vertices,
+        })
+    }
+}
+
+impl Renderer for State {
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    fn update(&mut self, triangles: &[LogicalPosition<f32>]) {
+        let vert: Vec<Vertex> = triangles.iter()
+            .map(|pos| {
+                let tex = LogicalPosition::new((pos.x + 1.0) / 2.0, 1.0 - (pos.y + 1.0) / 2.0);
+                Vertex::new(*pos, tex)
+            })
+            .collect();
+
+        self.overlay_vertices.write(&self.device, &self.queue, &vert);
+    }
+
+    fn clear_selection(&mut self) {
+        self.overlay_vertices.num_vertices = 0;
+    }
+
+    fn update_loupe(&mut self, pos1: LogicalPosition<f32>, pos2: LogicalPosition<f32>, tex1: LogicalPosition<f32>, tex2: LogicalPosition<f32>) {
+        self.loupe_vertices.write(&self.device, &self.queue, &render::quad(pos1, pos2, tex1, tex2));
+    }
+
+    fn update_text(&mut self, vertices: &[Vertex]) {
+        self.text_vertices.write(&self.device, &self.queue, vertices);
+    }
+
+    fn update_marks(&mut self, vert: &[ColorVertex]) {
+        self.mark_vertices.write(&self.device, &self.queue, vert);
+    }
+
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = self.surface.get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.0,
+                            g: 0.0,
+                            b: 0.0,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.theme_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.draw(0..self.num_vertices, 0..1);
+            if self.overlay_vertices.num_vertices > 0 {
+                render_pass.set_pipeline(&self.overlay_render_pipeline);
+                render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+                self.overlay_vertices.draw(&mut render_pass);
+            }
+            if self.loupe_vertices.num_vertices > 0 {
+                render_pass.set_pipeline(&self.loupe_render_pipeline);
+                render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+                self.loupe_vertices.draw(&mut render_pass);
+            }
+            if self.mark_vertices.num_vertices > 0 {
+                render_pass.set_pipeline(&self.mark_render_pipeline);
+                self.mark_vertices.draw(&mut render_pass);
+            }
+            if self.text_vertices.num_vertices > 0 {
+                render_pass.set_pipeline(&self.text_render_pipeline);
+                render_pass.set_bind_group(0, &self.text_bind_group, &[]);
+                self.text_vertices.draw(&mut render_pass);
+            }
+        }
+    
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+    
+        Ok(())
+    }
+}
+
+/// A vertex buffer that is rewritten in place, it is only replaced when the vertices
+/// no longer fit.
+struct VertexBuffer {
+    label: &'static str,
+    buffer: wgpu::Buffer,
+    capacity: wgpu::BufferAddress,
+    num_vertices: u32,
+}
+
+impl VertexBuffer {
+    fn new(device: &wgpu::Device, label: &'static str, capacity: wgpu::BufferAddress) -> Self {
+        Self { label, buffer: create_vertex_buffer(device, label, capacity), capacity, num_vertices: 0 }
+    }
+
+    fn write<T: bytemuck::Pod>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[T]) {
+        let contents: &[u8] = bytemuck::cast_slice(vertices);
+        let size = contents.len() as wgpu::BufferAddress;
+        if size > self.capacity {
+            // Doubling keeps a growing lasso or label from needing a new buffer every frame.
+            self.capacity = size.next_power_of_two();
+            self.buffer = create_vertex_buffer(device, self.label, self.capacity);
+        }
+        if size > 0 {
+            queue.write_buffer(&self.buffer, 0, contents);
+        }
+        self.num_vertices = vertices.len() as u32;
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, label: &str, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,106 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use winit::dpi::{LogicalPosition, PhysicalSize};
+
+use crate::render::{self, Vertex};
+
+/// How many screen pixels a font pixel covers.
+pub const SCALE: u32 = 2;
+// Glyph cells are 5 by 7 pixels with a pixel of space to the right and below.
+const CELL_WIDTH: u32 = 6;
+const CELL_HEIGHT: u32 = 8;
+
+// The classic 5x7 font for printable ASCII, one byte per column with the top row
+// in the lowest bit.
+const ASCII: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00], [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E], [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x32],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78], [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08], [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x02, 0x01, 0x02, 0x04, 0x02],
+];
+// Glyphs beyond ASCII, stored after it in the atlas.
+const EXTRA: [(char, [u8; 5]); 2] = [
+    ('×', [0x22, 0x14, 0x08, 0x14, 0x22]),
+    ('°', [0x00, 0x06, 0x09, 0x09, 0x06]),
+];
+
+// Where the glyph for `c` is in the atlas, unknown characters show as '?'.
+fn index(c: char) -> u32 {
+    match c {
+        ' '..='~' => c as u32 - ' ' as u32,
+        _ => match EXTRA.iter().position(|(extra, _)| *extra == c) {
+            Some(i) => (ASCII.len() + i) as u32,
+            None => '?' as u32 - ' ' as u32,
+        },
+    }
+}
+
+/// All glyphs side by side, white on black.
+pub fn atlas() -> DynamicImage {
+    let glyphs: Vec<[u8; 5]> = ASCII.iter().copied().chain(EXTRA.iter().map(|(_, columns)| *columns)).collect();
+    let mut atlas = RgbaImage::from_pixel(glyphs.len() as u32 * CELL_WIDTH, CELL_HEIGHT, Rgba([0, 0, 0, 255]));
+    for (i, columns) in glyphs.iter().enumerate() {
+        for (x, column) in columns.iter().enumerate() {
+            for y in 0..7 {
+                if column & 1 << y != 0 {
+                    atlas.put_pixel(i as u32 * CELL_WIDTH + x as u32, y, Rgba([255, 255, 255, 255]));
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(atlas)
+}
+
+/// Size of a label showing `text`, in screen pixels.
+pub fn size(text: &str) -> PhysicalSize<u32> {
+    // A blank cell on each side keeps the text off the edges.
+    PhysicalSize::new((text.chars().count() as u32 + 2) * CELL_WIDTH * SCALE, (CELL_HEIGHT + 2) * SCALE)
+}
+
+/// Triangles drawing `text` as a label with its top left corner at `x`, `y` on a `size` screen.
+pub fn vertices(text: &str, x: i32, y: i32, size: PhysicalSize<u32>) -> Vec<Vertex> {
+    let atlas_width = (ASCII.len() + EXTRA.len()) as f32 * CELL_WIDTH as f32;
+    let to_clip = |x: i32, y: i32| LogicalPosition::new((x as f32 / size.width as f32) * 2.0 - 1.0, 1.0 - (y as f32 / size.height as f32) * 2.0);
+    let (cell_width, cell_height) = ((CELL_WIDTH * SCALE) as i32, (CELL_HEIGHT * SCALE) as i32);
+    // Half a blank row above and below the glyphs, taken from the space below a glyph.
+    let margin = SCALE as i32;
+    let blank = |x: i32, width: i32, top: i32, bottom: i32| render::quad(
+        to_clip(x, top), to_clip(x + width, bottom), LogicalPosition::new(0.0, 0.9), LogicalPosition::new(0.0, 0.9),
+    );
+
+    let mut vertices = vec![];
+    let count = text.chars().count() as i32;
+    let right = x + (count + 2) * cell_width;
+    vertices.extend(blank(x, cell_width, y, y + cell_height + 2 * margin));
+    vertices.extend(blank(right - cell_width, cell_width, y, y + cell_height + 2 * margin));
+    vertices.extend(blank(x + cell_width, count * cell_width, y, y + margin));
+    vertices.extend(blank(x + cell_width, count * cell_width, y + margin + cell_height, y + cell_height + 2 * margin));
+    for (i, c) in text.chars().enumerate() {
+        let left = x + (i as i32 + 1) * cell_width;
+        let u = index(c) as f32 * CELL_WIDTH as f32 / atlas_width;
+        vertices.extend(render::quad(
+            to_clip(left, y + margin), to_clip(left + cell_width, y + margin + cell_height),
+            LogicalPosition::new(u, 0.0), LogicalPosition::new(u + CELL_WIDTH as f32 / atlas_width, 1.0),
+        ));
+    }
+    vertices
+}
@@ -0,0 +1,30 @@
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+[[group(0), binding(0)]]
+var t_atlas: texture_2d<f32>;
+
+// Glyphs are scaled up by whole pixels, so they are looked up directly to keep them sharp.
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_atlas));
+    let texel = min(in.tex_coords * size, size - vec2<f32>(1.0, 1.0));
+    return textureLoad(t_atlas, vec2<i32>(floor(texel)), 0);
+}